@@ -95,5 +95,9 @@ impl SoundSource for Source {
 
     fn set_volume(&mut self, volume: f32) {
         self.volume = volume;
+
+        if let Some(sink) = &self.sink {
+            sink.set_volume(volume);
+        }
     }
 }
\ No newline at end of file
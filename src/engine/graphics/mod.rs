@@ -7,7 +7,7 @@ use glium::{
     vertex::VertexBuffer,
     index::IndexBuffer,
 };
-use serde::Deserialize;
+use serde::{Serialize, Serializer, Deserialize};
 
 pub mod spritebatch;
 pub mod text;
@@ -34,6 +34,12 @@ pub struct Color {
     a: f32,
 }
 
+impl Serialize for Color {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        [self.r, self.g, self.b, self.a].serialize(serializer)
+    }
+}
+
 impl Into<[f32; 4]> for Color {
     fn into(self) -> [f32; 4] {
         [self.r, self.g, self.b, self.a]
@@ -41,6 +47,12 @@ impl Into<[f32; 4]> for Color {
 }
 
 impl Color {
+    /// Multiplies the alpha value.
+    pub fn fade(mut self, alpha: f32) -> Color {
+        self.a *= alpha;
+        self
+    }
+
     fn combine(mut self, color: Color) -> Color {
         self.r *= color.r;
         self.g *= color.g;
@@ -58,6 +70,12 @@ pub struct Rect {
     pub h: f32,
 }
 
+impl Serialize for Rect {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        [self.x, self.y, self.w, self.h].serialize(serializer)
+    }
+}
+
 impl Rect {
     pub fn new(x: f32, y: f32, w: f32, h: f32) -> Rect {
         Rect {
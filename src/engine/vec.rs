@@ -1,4 +1,4 @@
-use serde::Deserialize;
+use serde::{Serialize, Serializer, Deserialize};
 
 #[derive(Copy, Clone, Debug, Deserialize)]
 pub struct Vec2<T> {
@@ -15,6 +15,12 @@ impl<T> Vec2<T> {
     }
 }
 
+impl<T: Serialize> Serialize for Vec2<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        (&self.x, &self.y).serialize(serializer)
+    }
+}
+
 pub type Vec2f = Vec2<f32>;
 pub type Vec2i = Vec2<i32>;
 pub type Vec2u = Vec2<u32>;
\ No newline at end of file
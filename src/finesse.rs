@@ -0,0 +1,210 @@
+use std::collections::{HashSet, VecDeque};
+
+use crate::tetrimino::Tetrimino;
+use crate::rotation::RotationSystem;
+use crate::rules::Board;
+use crate::map::Map;
+use crate::simulation::{Action, Input};
+
+/// Inputs counted for finesse (a held shift key only counts once).
+const COUNTED: [Action; 4] = [Action::MoveLeft, Action::MoveRight, Action::RotateLeft, Action::RotateRight];
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum FinesseKey {
+    Left,
+    Right,
+    /// Shift to the wall (key held).
+    DasLeft,
+    DasRight,
+    RotateLeft,
+    RotateRight,
+}
+
+impl FinesseKey {
+    /// Rotations first, so that optimal sequences rotate before shifting.
+    const ALL: [FinesseKey; 6] = [
+        FinesseKey::RotateRight,
+        FinesseKey::RotateLeft,
+        FinesseKey::Left,
+        FinesseKey::Right,
+        FinesseKey::DasLeft,
+        FinesseKey::DasRight,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            FinesseKey::Left => "L",
+            FinesseKey::Right => "R",
+            FinesseKey::DasLeft => "DAS L",
+            FinesseKey::DasRight => "DAS R",
+            FinesseKey::RotateLeft => "CCW",
+            FinesseKey::RotateRight => "CW",
+        }
+    }
+}
+
+/// Counts the key presses used for the current piece.
+#[derive(Default)]
+pub struct FinesseTracker {
+    previous: Input,
+    presses: usize,
+}
+
+impl FinesseTracker {
+    pub fn input(&mut self, input: Input) {
+        self.presses += COUNTED.iter()
+            .filter(|&&action| input.contains(action) && !self.previous.contains(action))
+            .count();
+        self.previous = input;
+    }
+
+    pub fn presses(&self) -> usize {
+        self.presses
+    }
+
+    pub fn reset(&mut self) {
+        self.presses = 0;
+    }
+}
+
+/// Leftmost column and normalized tiles (two orientations with the same cells are the same placement).
+fn footprint(tet: &Tetrimino) -> (i32, Vec<(i32, i32)>) {
+    let min_x = tet.cells().map(|(x, _)| x).min().unwrap_or(0);
+    let min_y = tet.cells().map(|(_, y)| y).min().unwrap_or(0);
+    let tiles = tet.cells().map(|(x, y)| (x - min_x, y - min_y)).collect();
+    (min_x, tiles)
+}
+
+fn apply_key(rotation: &dyn RotationSystem, map: &Map, tet: &Tetrimino, key: FinesseKey) -> Option<Tetrimino> {
+    let mut next = tet.clone();
+
+    let moved = match key {
+        FinesseKey::Left => next.mov(map, -1, 0),
+        FinesseKey::Right => next.mov(map, 1, 0),
+        FinesseKey::DasLeft | FinesseKey::DasRight => {
+            let x_off = if key == FinesseKey::DasLeft { -1 } else { 1 };
+            let mut moved = false;
+            while next.mov(map, x_off, 0) {
+                moved = true;
+            }
+            moved
+        },
+        FinesseKey::RotateLeft | FinesseKey::RotateRight => match rotation.rotate(tet, map, key == FinesseKey::RotateRight) {
+            Some(rotated) => {
+                next = rotated;
+                true
+            },
+            None => false,
+        },
+    };
+
+    if moved { Some(next) } else { None }
+}
+
+/// Shortest key sequence which brings a freshly spawned piece above the placement of `locked` (on an empty board).
+pub fn optimal_keys(rotation: &dyn RotationSystem, board: &Board, locked: &Tetrimino) -> Option<Vec<FinesseKey>> {
+    let map = Map::new(board);
+    let target = footprint(locked);
+
+    // away from the ceiling, so that kicks behave like they do in the air
+    let mut start = rotation.spawn(locked.piece, board);
+    start.pos.y += (board.visible_height / 2) as i32;
+
+    let mut visited = HashSet::new();
+    let mut queue = VecDeque::new();
+    visited.insert((start.pos.x, start.pos.y, start.orientation as usize));
+    queue.push_back((start, Vec::new()));
+
+    while let Some((tet, keys)) = queue.pop_front() {
+        if footprint(&tet) == target {
+            return Some(keys);
+        }
+
+        for &key in FinesseKey::ALL.iter() {
+            if let Some(next) = apply_key(rotation, &map, &tet, key) {
+                if visited.insert((next.pos.x, next.pos.y, next.orientation as usize)) {
+                    let mut next_keys = keys.clone();
+                    next_keys.push(key);
+                    queue.push_back((next, next_keys));
+                }
+            }
+        }
+    }
+
+    None
+}
+
+/// The optimal keys if more keys than necessary were pressed.
+pub fn check(rotation: &dyn RotationSystem, board: &Board, locked: &Tetrimino, presses: usize) -> Option<Vec<FinesseKey>> {
+    optimal_keys(rotation, board, locked).filter(|keys| keys.len() < presses)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tetrimino::TileType;
+    use crate::rotation::{self, RotationSystemType};
+
+    /// Keys for each leftmost column of the spawn orientation (SRS, standard board).
+    const SPAWN_ORIENTATION_KEYS: [(TileType, &[usize]); 7] = [
+        (TileType::I, &[1, 2, 1, 0, 1, 2, 1]),
+        (TileType::O, &[1, 2, 2, 1, 0, 1, 2, 2, 1]),
+        (TileType::T, &[1, 2, 1, 0, 1, 2, 2, 1]),
+        (TileType::S, &[1, 2, 1, 0, 1, 2, 2, 1]),
+        (TileType::Z, &[1, 2, 1, 0, 1, 2, 2, 1]),
+        (TileType::J, &[1, 2, 1, 0, 1, 2, 2, 1]),
+        (TileType::L, &[1, 2, 1, 0, 1, 2, 2, 1]),
+    ];
+
+    /// The spawned piece moved so that its leftmost tile is in `column`.
+    fn placed(rotation: &dyn RotationSystem, board: &Board, piece: usize, column: i32) -> Tetrimino {
+        let mut tet = rotation.spawn(piece, board);
+        let (min_x, _) = footprint(&tet);
+        tet.pos.x += column - min_x;
+        tet
+    }
+
+    #[test]
+    fn optimal_keys_on_an_empty_board() {
+        let rotation = rotation::create(RotationSystemType::RotationSRS);
+        let board = Board::default();
+
+        for &(tile_type, expected) in SPAWN_ORIENTATION_KEYS.iter() {
+            let keys: Vec<usize> = (0..expected.len() as i32)
+                .map(|column| {
+                    let locked = placed(rotation.as_ref(), &board, tile_type as usize, column);
+                    optimal_keys(rotation.as_ref(), &board, &locked).unwrap().len()
+                })
+                .collect();
+            assert_eq!(keys, expected, "{:?}", tile_type);
+        }
+    }
+
+    #[test]
+    fn tracker_counts_a_fault() {
+        let rotation = rotation::create(RotationSystemType::RotationSRS);
+        let board = Board::default();
+        let locked = placed(rotation.as_ref(), &board, TileType::T as usize, 2);
+
+        let mut left = Input::default();
+        left.set(Action::MoveLeft);
+        let mut right = Input::default();
+        right.set(Action::MoveRight);
+
+        // a held key counts once
+        let mut tracker = FinesseTracker::default();
+        for &input in [left, left, left, Input::default()].iter() {
+            tracker.input(input);
+        }
+        assert_eq!(tracker.presses(), 1);
+        assert!(check(rotation.as_ref(), &board, &locked, tracker.presses()).is_none());
+
+        // right, then left twice instead of a single left
+        tracker.reset();
+        for &input in [right, Input::default(), left, Input::default(), left].iter() {
+            tracker.input(input);
+        }
+        assert_eq!(tracker.presses(), 3);
+        assert_eq!(check(rotation.as_ref(), &board, &locked, tracker.presses()), Some(vec![FinesseKey::Left]));
+    }
+}
@@ -0,0 +1,49 @@
+use rand::{
+    rngs::StdRng,
+    RngCore, SeedableRng,
+};
+
+/// Seed for garbage holes, independent of the tetrimino sequence of the same game seed.
+pub fn seed(seed: [u8; 32]) -> [u8; 32] {
+    seed.map(|byte| !byte)
+}
+
+/// Generates the hole column of each garbage row.
+pub struct GarbageGenerator {
+    rng: StdRng,
+    width: usize,
+    hole_change_probability: f32,
+    hole: Option<usize>,
+}
+
+impl GarbageGenerator {
+    pub fn new(seed: [u8; 32], width: usize, hole_change_probability: f32) -> GarbageGenerator {
+        GarbageGenerator {
+            rng: StdRng::from_seed(seed),
+            width,
+            hole_change_probability,
+            hole: None,
+        }
+    }
+
+    pub fn next_hole(&mut self) -> usize {
+        let current = self.hole;
+        let hole = match current {
+            None => (self.rng.next_u32() as usize) % self.width,
+            Some(hole) if self.width > 1 && self.change_hole() => {
+                // any other column
+                let value = (self.rng.next_u32() as usize) % (self.width - 1);
+                if value >= hole { value + 1 } else { value }
+            },
+            Some(hole) => hole,
+        };
+
+        self.hole = Some(hole);
+        hole
+    }
+
+    fn change_hole(&mut self) -> bool {
+        let value = self.rng.next_u32() as f32 / u32::MAX as f32;
+        value < self.hole_change_probability
+    }
+}
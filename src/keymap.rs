@@ -0,0 +1,179 @@
+use serde::{Serialize, Deserialize};
+
+use crate::engine::event::KeyCode;
+use crate::simulation::Action;
+
+/// Keys the game screen uses itself (pause, restart, menu), which cannot be bound.
+pub const RESERVED_KEYS: [KeyCode; 5] = [
+    KeyCode::Escape,
+    KeyCode::F1,
+    KeyCode::P,
+    KeyCode::R,
+    KeyCode::Return,
+];
+
+/// Actions a player can bind keys to (`Action::Drop` is only used by bots).
+pub const BINDABLE_ACTIONS: [Action; 7] = [
+    Action::RotateLeft,
+    Action::RotateRight,
+    Action::MoveLeft,
+    Action::MoveRight,
+    Action::SoftDrop,
+    Action::HardDrop,
+    Action::Hold,
+];
+
+/// Keys bound to each action of a player (any number per action).
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Keymap {
+    pub rotate_left: Vec<KeyCode>,
+    pub rotate_right: Vec<KeyCode>,
+    pub move_left: Vec<KeyCode>,
+    pub move_right: Vec<KeyCode>,
+    pub soft_drop: Vec<KeyCode>,
+    pub hard_drop: Vec<KeyCode>,
+    pub hold: Vec<KeyCode>,
+}
+
+impl Keymap {
+    pub fn keys(&self, action: Action) -> &[KeyCode] {
+        match action {
+            Action::RotateLeft => &self.rotate_left,
+            Action::RotateRight => &self.rotate_right,
+            Action::MoveLeft => &self.move_left,
+            Action::MoveRight => &self.move_right,
+            Action::SoftDrop => &self.soft_drop,
+            Action::HardDrop => &self.hard_drop,
+            Action::Hold => &self.hold,
+            Action::Drop => &[],
+        }
+    }
+
+    fn keys_mut(&mut self, action: Action) -> Option<&mut Vec<KeyCode>> {
+        match action {
+            Action::RotateLeft => Some(&mut self.rotate_left),
+            Action::RotateRight => Some(&mut self.rotate_right),
+            Action::MoveLeft => Some(&mut self.move_left),
+            Action::MoveRight => Some(&mut self.move_right),
+            Action::SoftDrop => Some(&mut self.soft_drop),
+            Action::HardDrop => Some(&mut self.hard_drop),
+            Action::Hold => Some(&mut self.hold),
+            Action::Drop => None,
+        }
+    }
+
+    /// The action the key is bound to.
+    pub fn action(&self, key: KeyCode) -> Option<Action> {
+        BINDABLE_ACTIONS.iter()
+            .copied()
+            .find(|&action| self.keys(action).contains(&key))
+    }
+
+    pub fn bind(&mut self, action: Action, key: KeyCode) {
+        if let Some(keys) = self.keys_mut(action) {
+            if !keys.contains(&key) {
+                keys.push(key);
+            }
+        }
+    }
+
+    pub fn clear(&mut self, action: Action) {
+        if let Some(keys) = self.keys_mut(action) {
+            keys.clear();
+        }
+    }
+}
+
+/// A key bound twice (or a reserved key bound at all).
+#[derive(Copy, Clone, PartialEq)]
+pub enum Conflict {
+    Reserved,
+    /// Player (index of the keymap) and action the key is already bound to.
+    Bound(usize, Action),
+}
+
+/// Checks whether the key can be bound to the action of the player.
+pub fn conflict(keymaps: &[Keymap], player: usize, action: Action, key: KeyCode) -> Option<Conflict> {
+    if RESERVED_KEYS.contains(&key) {
+        return Some(Conflict::Reserved);
+    }
+
+    keymaps.iter()
+        .enumerate()
+        .filter_map(|(index, keymap)| keymap.action(key).map(|bound| (index, bound)))
+        .find(|&(index, bound)| index != player || bound != action)
+        .map(|(index, bound)| Conflict::Bound(index, bound))
+}
+
+/// The first key which is reserved or bound more than once (over all keymaps).
+pub fn find_conflict(keymaps: &[Keymap]) -> Option<KeyCode> {
+    let mut keys = Vec::new();
+
+    for keymap in keymaps {
+        for &action in BINDABLE_ACTIONS.iter() {
+            for &key in keymap.keys(action) {
+                if RESERVED_KEYS.contains(&key) || keys.contains(&key) {
+                    return Some(key);
+                }
+                keys.push(key);
+            }
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keymap(keys: [KeyCode; 7]) -> Keymap {
+        Keymap {
+            rotate_left: vec![keys[0]],
+            rotate_right: vec![keys[1]],
+            move_left: vec![keys[2]],
+            move_right: vec![keys[3]],
+            soft_drop: vec![keys[4]],
+            hard_drop: vec![keys[5]],
+            hold: vec![keys[6]],
+        }
+    }
+
+    fn keymaps() -> Vec<Keymap> {
+        vec![
+            keymap([KeyCode::Z, KeyCode::X, KeyCode::Left, KeyCode::Right, KeyCode::Down, KeyCode::Up, KeyCode::C]),
+            keymap([KeyCode::Q, KeyCode::E, KeyCode::A, KeyCode::D, KeyCode::S, KeyCode::W, KeyCode::Tab]),
+        ]
+    }
+
+    #[test]
+    fn same_player_duplicate() {
+        let mut keymaps = keymaps();
+        assert!(conflict(&keymaps, 0, Action::Hold, KeyCode::Z) == Some(Conflict::Bound(0, Action::RotateLeft)));
+        // binding the key again to its own action is fine
+        assert!(conflict(&keymaps, 0, Action::RotateLeft, KeyCode::Z).is_none());
+        assert_eq!(find_conflict(&keymaps), None);
+
+        keymaps[0].bind(Action::Hold, KeyCode::Z);
+        assert_eq!(find_conflict(&keymaps), Some(KeyCode::Z));
+    }
+
+    #[test]
+    fn cross_player_duplicate() {
+        let mut keymaps = keymaps();
+        assert!(conflict(&keymaps, 1, Action::RotateLeft, KeyCode::Z) == Some(Conflict::Bound(0, Action::RotateLeft)));
+        assert!(conflict(&keymaps, 0, Action::MoveLeft, KeyCode::A) == Some(Conflict::Bound(1, Action::MoveLeft)));
+
+        keymaps[1].bind(Action::MoveLeft, KeyCode::Left);
+        assert_eq!(find_conflict(&keymaps), Some(KeyCode::Left));
+    }
+
+    #[test]
+    fn reserved_key() {
+        let mut keymaps = keymaps();
+        assert!(conflict(&keymaps, 0, Action::Hold, KeyCode::P) == Some(Conflict::Reserved));
+
+        keymaps[1].bind(Action::Hold, KeyCode::Escape);
+        assert_eq!(find_conflict(&keymaps), Some(KeyCode::Escape));
+    }
+}
@@ -0,0 +1,223 @@
+use serde::{Serialize, Deserialize};
+use serde_json::Result;
+use std::io::{Read, Write};
+
+use crate::rules::{Rules, Board, GameMode, TopOut};
+use crate::random::RandomGeneratorType;
+use crate::rotation::RotationSystemType;
+
+/// Entries kept per table.
+pub const TABLE_SIZE: usize = 10;
+
+/// Mode and the rules a table is kept for (games are only ranked against games with the same difficulty).
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct Category {
+    pub mode: GameMode,
+    pub random_generator: RandomGeneratorType,
+    pub rotation_system: RotationSystemType,
+    pub hard_drop_enabled: bool,
+    pub board: Board,
+    pub start_level: usize,
+    pub top_out: TopOut,
+    pub classic_timing: bool,
+    /// Name of the custom piece set (`None` for the tetriminos).
+    pub piece_set: Option<String>,
+}
+
+impl Category {
+    pub fn new(rules: &Rules) -> Category {
+        Category {
+            mode: rules.mode,
+            random_generator: rules.random_generator,
+            rotation_system: rules.rotation_system,
+            hard_drop_enabled: rules.hard_drop_enabled,
+            board: rules.board(),
+            start_level: rules.start_level,
+            top_out: rules.top_out,
+            classic_timing: rules.classic_timing,
+            piece_set: rules.piece_set.as_ref().map(|piece_set| piece_set.name.clone()),
+        }
+    }
+
+    /// Sprint and dig are ranked by time, the other modes by score.
+    pub fn is_timed(&self) -> bool {
+        matches!(self.mode, GameMode::Sprint { .. } | GameMode::Dig { .. })
+    }
+
+    /// Whether a game which is over enters the table (timed modes and ultra have to be finished).
+    pub fn is_eligible(&self, finished: bool) -> bool {
+        finished || self.mode == GameMode::Marathon
+    }
+
+    /// Whether `a` ranks before `b` (ties keep the older entry first).
+    fn ranks_before(&self, a: &Entry, b: &Entry) -> bool {
+        if self.is_timed() {
+            a.frames < b.frames
+        } else {
+            a.score > b.score
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub nickname: String,
+    pub score: usize,
+    pub lines: usize,
+    pub level: usize,
+    pub frames: usize,
+    /// Seconds since the unix epoch.
+    pub date: u64,
+    pub seed: [u8; 32],
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Table {
+    pub category: Category,
+    /// Best entry first.
+    pub entries: Vec<Entry>,
+}
+
+/// High scores of all players on this machine.
+#[derive(Default, Serialize, Deserialize)]
+pub struct Leaderboard {
+    tables: Vec<Table>,
+}
+
+impl Leaderboard {
+    pub fn tables(&self) -> &[Table] {
+        &self.tables
+    }
+
+    pub fn table(&self, category: &Category) -> Option<&Table> {
+        self.tables.iter().find(|table| table.category == *category)
+    }
+
+    /// Returns the rank (index) of the entry if it made it into the table.
+    pub fn submit(&mut self, category: Category, entry: Entry) -> Option<usize> {
+        let index = match self.tables.iter().position(|table| table.category == category) {
+            Some(index) => index,
+            None => {
+                self.tables.push(Table {
+                    category: category.clone(),
+                    entries: Vec::new(),
+                });
+                self.tables.len() - 1
+            },
+        };
+
+        let entries = &mut self.tables[index].entries;
+        let rank = entries.iter()
+            .position(|other| category.ranks_before(&entry, other))
+            .unwrap_or(entries.len());
+
+        if rank >= TABLE_SIZE {
+            return None;
+        }
+
+        entries.insert(rank, entry);
+        entries.truncate(TABLE_SIZE);
+        Some(rank)
+    }
+
+    pub fn save<W: Write>(&self, writer: W) -> Result<()> {
+        serde_json::to_writer(writer, self)
+    }
+}
+
+pub fn load<R: Read>(reader: R) -> Result<Leaderboard> {
+    serde_json::from_reader(reader)
+}
+
+/// Formats seconds since the unix epoch as `yyyy-mm-dd` (UTC).
+pub fn format_date(date: u64) -> String {
+    // civil from days (proleptic gregorian calendar)
+    let days = (date / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn category(mode: GameMode) -> Category {
+        Category {
+            mode,
+            random_generator: RandomGeneratorType::RandomBag,
+            rotation_system: RotationSystemType::RotationSRS,
+            hard_drop_enabled: true,
+            board: Board::default(),
+            start_level: 0,
+            top_out: TopOut::Guideline,
+            classic_timing: false,
+            piece_set: None,
+        }
+    }
+
+    fn entry(nickname: &str, score: usize, frames: usize) -> Entry {
+        Entry {
+            nickname: nickname.to_string(),
+            score,
+            lines: 0,
+            level: 0,
+            frames,
+            date: 0,
+            seed: [0; 32],
+        }
+    }
+
+    fn nicknames(leaderboard: &Leaderboard, category: &Category) -> Vec<String> {
+        leaderboard.table(category).unwrap().entries.iter().map(|entry| entry.nickname.clone()).collect()
+    }
+
+    #[test]
+    fn submit_orders_by_score_and_keeps_the_best() {
+        let marathon = category(GameMode::Marathon);
+        let mut leaderboard = Leaderboard::default();
+
+        for score in 0..TABLE_SIZE {
+            assert_eq!(leaderboard.submit(marathon.clone(), entry(&score.to_string(), score * 100, 0)), Some(0));
+        }
+        // ties rank after the older entry
+        assert_eq!(leaderboard.submit(marathon.clone(), entry("tie", 500, 0)), Some(5));
+        // the table is full, the lowest score drops out
+        assert_eq!(leaderboard.submit(marathon.clone(), entry("low", 0, 0)), None);
+
+        let names = nicknames(&leaderboard, &marathon);
+        assert_eq!(names.len(), TABLE_SIZE);
+        assert_eq!(names[..7], ["9", "8", "7", "6", "5", "tie", "4"]);
+        assert_eq!(names[TABLE_SIZE - 1], "1");
+    }
+
+    #[test]
+    fn submit_orders_timed_modes_by_frames() {
+        let sprint = category(GameMode::Sprint { lines: 40 });
+        let other = Category { start_level: 5, ..sprint.clone() };
+        let mut leaderboard = Leaderboard::default();
+
+        assert_eq!(leaderboard.submit(sprint.clone(), entry("slow", 0, 9000)), Some(0));
+        assert_eq!(leaderboard.submit(sprint.clone(), entry("fast", 0, 6000)), Some(0));
+        // other rules are kept in their own table
+        assert_eq!(leaderboard.submit(other.clone(), entry("level", 0, 9999)), Some(0));
+
+        assert_eq!(nicknames(&leaderboard, &sprint), ["fast", "slow"]);
+        assert_eq!(nicknames(&leaderboard, &other), ["level"]);
+    }
+
+    #[test]
+    fn format_date_is_utc() {
+        assert_eq!(format_date(0), "1970-01-01");
+        assert_eq!(format_date(951_782_400), "2000-02-29");
+        assert_eq!(format_date(1_709_251_199), "2024-02-29");
+        assert_eq!(format_date(1_735_689_600), "2025-01-01");
+    }
+}
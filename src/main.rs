@@ -18,8 +18,20 @@ use std::{
 mod engine;
 mod tetrimino;
 mod settings;
+mod keymap;
+mod rules;
 mod random;
+mod rotation;
+mod piece;
 mod map;
+mod garbage;
+mod finesse;
+mod simulation;
+mod replay;
+mod stats;
+mod records;
+mod leaderboard;
+mod net;
 pub mod state;
 
 use state::StateHandler;
@@ -27,26 +39,10 @@ use state::StateHandler;
 fn main() {
     // TODO:
     // - popup for each game instance
-    // - menu (show: start)
-    
-    // ------------------------------------------------------------------------------------------------
-    // MULTIPLAYER
-    // - both player are added to the local leaderboard
-    // ------------------------------------------------------------------------------------------------
-    // NETWORKING:
-    // - settings handshake
-    // - initial command (seed, nicknames)
-    // - ready command
-    // - ...
-    // ------------------------------------------------------------------------------------------------
 
     // ------------------------------------------------------------------------------------------------
     // OPTIONAL FEATURES:
-    // - menu (show: leaderboard OR ready toggle)
-    // - leaderboard
     // - sound effects
-    // - shadow piece
-    // - hold piece
     // ------------------------------------------------------------------------------------------------
     // OPTIONAL IMPROVMENTS:
     // - save generators history locally (only one local generator)
@@ -62,11 +58,34 @@ fn main() {
         "".to_string()
     };
 
-    let file = File::open(Path::new(&(path + "resources/settings.json")))
+    let file = File::open(settings::user_data_path(settings::SETTINGS_FILE))
         .expect("Could not load settings");
-    let settings = settings::load(file)
+    let mut settings = settings::load(file)
         .expect("Settings corrupted");
 
+    // load piece set (replaces the tetriminos of the rotation system)
+    if let Some(piece_set_file) = &settings.piece_set_file {
+        let file = File::open(Path::new(&(path + "resources/" + piece_set_file)))
+            .expect("Could not load piece set");
+        let piece_set = piece::load(file)
+            .expect("Piece set corrupted");
+        piece_set.validate_board(&settings.rules.board())
+            .expect("Piece set does not fit the board");
+        settings.rules.piece_set = Some(piece_set);
+    }
+
+    // load replay (playback mode): tetris --replay <file>
+    let args: Vec<String> = env::args().collect();
+    let replay = if args.len() >= 3 && args[1] == "--replay" {
+        let file = File::open(Path::new(&args[2]))
+            .expect("Could not load replay");
+        let replay = replay::load(file)
+            .expect("Replay corrupted");
+        Some(replay)
+    } else {
+        None
+    };
+
     // build context
     let mut ctx_builder = ContextBuilder::new("tetris", "");
 
@@ -82,7 +101,7 @@ fn main() {
 
     let background = settings.background();
     let window_mode = WindowMode::default()
-        .dimensions(background.w, background.h);
+        .dimensions(settings.window_width(), background.h);
 
     let (mut ctx, mut event_loop) = ctx_builder
         .window_setup(window_setup)
@@ -90,7 +109,7 @@ fn main() {
         .build()
         .expect("Could not create engine context!");
 
-    let mut handler = StateHandler::new(&mut ctx, settings)
+    let mut handler = StateHandler::new(&mut ctx, settings, replay)
         .expect("Could not create state handler!");
 
     // run
@@ -1,11 +1,7 @@
-use crate::engine::{
-    vec::Vec2f,
-    graphics::spritebatch::SpriteBatch,
-};
 use std::ops::Index;
 
 use crate::tetrimino::{TileType, Tetrimino};
-use crate::settings::{self, Settings};
+use crate::rules::Board;
 
 pub struct CompleteLines {
     data: Vec<usize>,
@@ -43,60 +39,111 @@ impl Index<usize> for CompleteLines {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq)]
 pub struct Map {
-    tiles: [TileType; settings::MAP_TILE_COUNT],
+    width: usize,
+    height: usize,
+    hidden_rows: usize,
+    tiles: Vec<TileType>,
+    /// Simulation frame in which each tile was locked.
+    lock_frames: Vec<usize>,
 }
 
 impl Map {
-    pub fn new() -> Map {
+    pub fn new(board: &Board) -> Map {
         Map {
-            tiles: [TileType::Empty; settings::MAP_TILE_COUNT],
+            width: board.width,
+            height: board.height(),
+            hidden_rows: board.hidden_rows,
+            tiles: vec![TileType::Empty; board.width * board.height()],
+            lock_frames: vec![0; board.width * board.height()],
         }
     }
 
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Height including the hidden rows.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Rows above the visible field (at the top of the map).
+    pub fn hidden_rows(&self) -> usize {
+        self.hidden_rows
+    }
+
+    pub fn visible_height(&self) -> usize {
+        self.height - self.hidden_rows
+    }
+
     pub fn get(&self, x: usize, y: usize) -> TileType {
-        self.tiles[settings::MAP_WIDTH * y + x]
+        self.tiles[self.width * y + x]
     }
 
     pub fn set(&mut self, x: usize, y: usize, tile_type: TileType) {
-        self.tiles[settings::MAP_WIDTH * y + x] = tile_type;
+        self.tiles[self.width * y + x] = tile_type;
+    }
+
+    pub fn lock_frame(&self, x: usize, y: usize) -> usize {
+        self.lock_frames[self.width * y + x]
+    }
+
+    /// Moves a tile within its column (with its lock frame).
+    fn move_tile(&mut self, x: usize, from_y: usize, to_y: usize) {
+        self.tiles[self.width * to_y + x] = self.tiles[self.width * from_y + x];
+        self.lock_frames[self.width * to_y + x] = self.lock_frames[self.width * from_y + x];
     }
 
     pub fn collision(&self, tet: &Tetrimino) -> bool {
-        for &tile in tet.tiles.iter() {
-            // Note: float casting can currently result in undefined behavior (so this check is temporary)
-            let x = tet.pos.x + tile.x;
-            let y = tet.pos.y + tile.y;
+        tet.cells().any(|(x, y)| self.occupied(x, y))
+    }
 
-            if x < 0.0 || y < 0.0 {
-                return true;
-            }
+    /// Whether the position is outside of the map or already filled.
+    pub fn occupied(&self, x: i32, y: i32) -> bool {
+        if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+            return true;
+        }
 
-            let x = (tet.pos.x + tile.x).round() as usize;
-            let y = (tet.pos.y + tile.y).round() as usize;
+        self.get(x as usize, y as usize) != TileType::Empty
+    }
 
-            // (x < 0 || y < 0) is tested within next check because of usize wrap-around
+    /// Pushes the stack up by one row and fills the bottom row with garbage (except for the hole),
+    /// locked in `frame`. Returns whether tiles were pushed out of the top.
+    pub fn push_garbage(&mut self, hole: usize, frame: usize) -> bool {
+        let overflow = (0..self.width).any(|x| self.get(x, 0) != TileType::Empty);
 
-            if x >= settings::MAP_WIDTH || y >= settings::MAP_HEIGHT {
-                return true;
+        for y in 1..self.height {
+            for x in 0..self.width {
+                self.move_tile(x, y, y - 1);
             }
+        }
 
-            if self.tiles[y * settings::MAP_WIDTH + x] != TileType::Empty {
-                return true;
-            }
+        let y = self.height - 1;
+        for x in 0..self.width {
+            let tile_type = if x == hole { TileType::Empty } else { TileType::Garbage };
+            self.set(x, y, tile_type);
+            self.lock_frames[self.width * y + x] = frame;
         }
 
-        false
+        overflow
+    }
+
+    /// Number of rows which still contain garbage.
+    pub fn garbage_rows(&self) -> usize {
+        (0..self.height)
+            .filter(|&y| (0..self.width).any(|x| self.get(x, y) == TileType::Garbage))
+            .count()
     }
 
     pub fn complete_lines(&self) -> CompleteLines {
         let mut lines = CompleteLines::new();
 
-        for y in (0..settings::MAP_HEIGHT).rev() {
+        for y in (0..self.height).rev() {
             let mut complete = true;
 
-            for x in 0..settings::MAP_WIDTH {
+            for x in 0..self.width {
                 if self.get(x, y) == TileType::Empty {
                     complete = false;
                     break;
@@ -119,40 +166,30 @@ impl Map {
         // remove complete lines
         for i in 0..lines.len() {
             for y in (lines[i + 1]..lines[i]).rev() {
-                for x in 0..settings::MAP_WIDTH {
-                    let tile_type = self.get(x, y);
-                    self.set(x, y + i + 1, tile_type);
+                for x in 0..self.width {
+                    self.move_tile(x, y, y + i + 1);
                 }
             }
         }
 
         //
         for i in 0..lines.len() {
-            for x in 0..settings::MAP_WIDTH {
+            for x in 0..self.width {
                 self.set(x, i, TileType::Empty);
             }
         }
     }
 
-    pub fn apply(&mut self, tet: &Tetrimino) {
-        for &pos in tet.tiles.iter() {
-            let x = (tet.pos.x + pos.x).round() as usize;
-            let y = (tet.pos.y + pos.y).round() as usize;
-
-            self.set(x, y, tet.tile_type);
-        }
-    }
-    
-    pub fn draw(&self, settings: &Settings, batch: &mut SpriteBatch, level: usize, map_position: &Vec2f) {
-        for y in 0..settings::MAP_HEIGHT {
-            for x in 0..settings::MAP_WIDTH {
-                let pos = Vec2f::new(x as f32, y as f32);
-                self.tiles[y * settings::MAP_WIDTH + x].draw_map(settings, batch, level, map_position, pos);
-            }
+    pub fn apply(&mut self, tet: &Tetrimino, frame: usize) {
+        for (x, y) in tet.cells() {
+            self.set(x as usize, y as usize, tet.tile_type);
+            self.lock_frames[self.width * y as usize + x as usize] = frame;
         }
     }
 
+
     pub fn reset(&mut self) {
-        self.tiles = [TileType::Empty; settings::MAP_TILE_COUNT];
+        self.tiles.iter_mut().for_each(|tile| *tile = TileType::Empty);
+        self.lock_frames.iter_mut().for_each(|frame| *frame = 0);
     }
 }
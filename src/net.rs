@@ -0,0 +1,206 @@
+use serde::{Serialize, Deserialize};
+use std::{
+    io::{self, BufRead, BufReader, ErrorKind, Write},
+    net::{TcpListener, TcpStream, ToSocketAddrs, Shutdown},
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+    time::Duration,
+};
+
+use crate::rules::{Rules, Handling};
+use crate::simulation::Input;
+
+/// Incremented whenever the messages change (both ends have to speak the same protocol).
+pub const PROTOCOL_VERSION: u32 = 2;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What the other end needs to know about a player.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Profile {
+    pub nickname: String,
+    /// Only used if the sender hosts.
+    pub rules: Rules,
+    pub handling: Handling,
+}
+
+/// Messages of the versus protocol, one JSON object per line.
+///
+/// Both ends start with `Hello` (the rules of the host are played), the host then sends `Start`
+/// for every round. A round begins once both ends sent `Ready`, then each end streams the input
+/// of every frame of its own player.
+#[derive(Clone, Serialize, Deserialize)]
+pub enum Message {
+    /// Settings handshake.
+    Hello {
+        version: u32,
+        profile: Box<Profile>,
+    },
+    /// Initial command of a round (sent by the host).
+    Start {
+        seed: [u8; 32],
+    },
+    /// The sender has reset its game and waits for the other end.
+    Ready,
+    /// Input of the next frame of the sender's player.
+    Frame {
+        input: Input,
+        /// Garbage rows the sender's player received before the frame.
+        #[serde(default)]
+        garbage: usize,
+    },
+}
+
+/// A listening socket which accepts the other player.
+pub struct Host {
+    listener: TcpListener,
+}
+
+impl Host {
+    pub fn bind(address: &str) -> io::Result<Host> {
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+
+        Ok(Host {
+            listener,
+        })
+    }
+
+    /// Returns the connection of the other player once they joined (never blocks).
+    pub fn accept(&self) -> io::Result<Option<Connection>> {
+        match self.listener.accept() {
+            Ok((stream, _)) => Connection::new(stream).map(Some),
+            Err(ref err) if err.kind() == ErrorKind::WouldBlock => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    #[cfg(test)]
+    fn address(&self) -> String {
+        self.listener.local_addr().unwrap().to_string()
+    }
+}
+
+/// Connects to a host in the background (the result is received once the attempt is over).
+pub fn join(address: &str) -> Receiver<io::Result<Connection>> {
+    let (sender, receiver) = mpsc::channel();
+    let address = address.to_string();
+
+    thread::spawn(move || {
+        let result = address.to_socket_addrs()
+            .and_then(|mut addresses| addresses.next().ok_or_else(|| io::Error::new(ErrorKind::InvalidInput, "no address")))
+            .and_then(|address| TcpStream::connect_timeout(&address, CONNECT_TIMEOUT))
+            .and_then(Connection::new);
+
+        // the receiver is gone if joining was cancelled
+        let _ = sender.send(result);
+    });
+
+    receiver
+}
+
+/// A connection to the other player. Messages are read on a separate thread, so receiving never blocks.
+pub struct Connection {
+    stream: TcpStream,
+    messages: Receiver<Message>,
+}
+
+impl Connection {
+    fn new(stream: TcpStream) -> io::Result<Connection> {
+        stream.set_nonblocking(false)?;
+        // inputs are sent every frame
+        stream.set_nodelay(true)?;
+
+        let reader = BufReader::new(stream.try_clone()?);
+        let (sender, messages) = mpsc::channel();
+
+        thread::spawn(move || {
+            for line in reader.lines() {
+                let message = match line.map(|line| serde_json::from_str(&line)) {
+                    Ok(Ok(message)) => message,
+                    // closed, broken or not speaking the protocol
+                    _ => break,
+                };
+
+                if sender.send(message).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Connection {
+            stream,
+            messages,
+        })
+    }
+
+    pub fn send(&mut self, message: &Message) -> io::Result<()> {
+        let mut line = serde_json::to_vec(message)?;
+        line.push(b'\n');
+        self.stream.write_all(&line)
+    }
+
+    /// The next received message (`None` if there is none yet), an error once the connection is closed.
+    pub fn receive(&mut self) -> io::Result<Option<Message>> {
+        match self.messages.try_recv() {
+            Ok(message) => Ok(Some(message)),
+            Err(TryRecvError::Empty) => Ok(None),
+            Err(TryRecvError::Disconnected) => Err(io::Error::new(ErrorKind::ConnectionAborted, "connection closed")),
+        }
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        // also ends the reading thread (and the connection of the other end)
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::Action;
+
+    fn receive(connection: &mut Connection) -> io::Result<Message> {
+        loop {
+            if let Some(message) = connection.receive()? {
+                return Ok(message);
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn localhost_exchange() {
+        let host = Host::bind("127.0.0.1:0").unwrap();
+        let joining = join(&host.address());
+
+        let mut host_end = loop {
+            if let Some(connection) = host.accept().unwrap() {
+                break connection;
+            }
+            thread::sleep(Duration::from_millis(1));
+        };
+        let mut join_end = joining.recv().unwrap().unwrap();
+
+        let seed = [7; 32];
+        let mut input = Input::default();
+        input.set(Action::HardDrop);
+
+        host_end.send(&Message::Start { seed }).unwrap();
+        join_end.send(&Message::Frame { input, garbage: 2 }).unwrap();
+
+        match receive(&mut join_end).unwrap() {
+            Message::Start { seed: received } => assert_eq!(received, seed),
+            _ => panic!("expected the start command"),
+        }
+        match receive(&mut host_end).unwrap() {
+            Message::Frame { input: received, garbage } => assert!(received == input && garbage == 2),
+            _ => panic!("expected a frame"),
+        }
+
+        drop(join_end);
+        assert!(receive(&mut host_end).is_err());
+    }
+}
@@ -0,0 +1,198 @@
+use serde::{
+    de::Error,
+    Serialize, Deserialize,
+};
+use serde_json::Result;
+use std::io::Read;
+
+use crate::tetrimino::{self, TileType, Orientation, Mask, MASK_SIZE};
+use crate::rotation::RotationSystem;
+use crate::rules::Board;
+use crate::map::Map;
+
+const NO_KICKS: [(i32, i32); 1] = [(0, 0)];
+
+/// Number of tile sprites a piece can use.
+const COLOR_COUNT: usize = 7;
+
+/// Kick offsets for each orientation: `[clockwise, counter clockwise][from]`.
+pub type KickTable = [[Vec<(i32, i32)>; 4]; 2];
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PieceDefinition {
+    pub name: String,
+    /// Tiles in spawn orientation (relative to the bounding box, y down).
+    pub cells: Vec<(i32, i32)>,
+    /// Side length of the bounding box the cells are rotated in.
+    pub size: i32,
+    /// Position of the bounding box at spawn (on a standard board).
+    pub spawn: (i32, i32),
+    /// Tile sprite (index of the tetrimino with the same color).
+    pub color: usize,
+    /// Offsets tested when rotating, none if missing.
+    #[serde(default)]
+    pub kicks: Option<KickTable>,
+}
+
+/// Polyominos which replace the tetriminos of the rotation system.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PieceSet {
+    pub name: String,
+    pub pieces: Vec<PieceDefinition>,
+}
+
+impl PieceSet {
+    fn validate(&self) -> std::result::Result<(), String> {
+        if self.pieces.is_empty() {
+            return Err(format!("piece set {} is empty", self.name));
+        }
+
+        for piece in self.pieces.iter() {
+            if piece.cells.is_empty() {
+                return Err(format!("piece {} has no cells", piece.name));
+            }
+
+            if piece.size < 1 || piece.size > MASK_SIZE {
+                return Err(format!("piece {} has size {} (1 to {})", piece.name, piece.size, MASK_SIZE));
+            }
+
+            if piece.cells.iter().any(|&(x, y)| x < 0 || y < 0 || x >= piece.size || y >= piece.size) {
+                return Err(format!("piece {} has cells outside of its bounding box", piece.name));
+            }
+
+            if piece.color >= COLOR_COUNT {
+                return Err(format!("piece {} has color {} (0 to {})", piece.name, piece.color, COLOR_COUNT - 1));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks that every piece spawns within the board (pieces wider than a narrow board do not).
+    pub fn validate_board(&self, board: &Board) -> std::result::Result<(), String> {
+        let rotation = CustomRotation::new(self);
+        let map = Map::new(board);
+
+        for (index, piece) in self.pieces.iter().enumerate() {
+            if map.collision(&rotation.spawn(index, board)) {
+                return Err(format!("piece {} does not fit a board of width {}", piece.name, board.width));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+struct CustomPiece {
+    tile_type: TileType,
+    masks: [Mask; 4],
+    spawn: (i32, i32),
+    kicks: Option<KickTable>,
+}
+
+impl CustomPiece {
+    fn new(definition: &PieceDefinition) -> CustomPiece {
+        // rotate clockwise within the bounding box
+        let mut cells = definition.cells.clone();
+        let mut masks = [0; 4];
+        for mask in masks.iter_mut() {
+            *mask = tetrimino::mask(&cells);
+            cells = cells.iter().map(|&(x, y)| (definition.size - 1 - y, x)).collect();
+        }
+
+        CustomPiece {
+            tile_type: TileType::from_usize(definition.color),
+            masks,
+            spawn: definition.spawn,
+            kicks: definition.kicks.clone(),
+        }
+    }
+}
+
+/// Rotation system of a piece set loaded from a file.
+pub struct CustomRotation {
+    pieces: Vec<CustomPiece>,
+}
+
+impl CustomRotation {
+    pub fn new(piece_set: &PieceSet) -> CustomRotation {
+        CustomRotation {
+            pieces: piece_set.pieces.iter().map(CustomPiece::new).collect(),
+        }
+    }
+}
+
+impl RotationSystem for CustomRotation {
+    fn piece_count(&self) -> usize {
+        self.pieces.len()
+    }
+
+    fn tile_type(&self, piece: usize) -> TileType {
+        self.pieces[piece].tile_type
+    }
+
+    fn mask(&self, piece: usize, orientation: Orientation) -> Mask {
+        self.pieces[piece].masks[orientation as usize]
+    }
+
+    fn spawn_position(&self, piece: usize) -> (i32, i32) {
+        self.pieces[piece].spawn
+    }
+
+    fn kicks(&self, piece: usize, from: Orientation, clockwise: bool) -> &[(i32, i32)] {
+        let direction = if clockwise { 0 } else { 1 };
+
+        match &self.pieces[piece].kicks {
+            Some(kicks) if !kicks[direction][from as usize].is_empty() => &kicks[direction][from as usize],
+            _ => &NO_KICKS,
+        }
+    }
+}
+
+pub fn load<R: Read>(reader: R) -> Result<PieceSet> {
+    let piece_set: PieceSet = serde_json::from_reader(reader)?;
+    piece_set.validate().map_err(serde_json::Error::custom)?;
+    Ok(piece_set)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+
+    fn load_bundled(name: &str) -> PieceSet {
+        let file = File::open(format!("{}/resources/pieces/{}", env!("CARGO_MANIFEST_DIR"), name)).unwrap();
+        load(file).unwrap()
+    }
+
+    #[test]
+    fn bundled_sets_fit_the_default_board() {
+        let board = Board::default();
+
+        for name in ["pentomino.json", "big.json"].iter() {
+            let piece_set = load_bundled(name);
+            assert!(piece_set.validate_board(&board).is_ok(), "{}", name);
+
+            let rotation = CustomRotation::new(&piece_set);
+            for piece in 0..rotation.piece_count() {
+                let mut map = Map::new(&board);
+                let mut tet = rotation.spawn(piece, &board);
+                assert!(!map.collision(&tet), "{}: piece {} collides at spawn", name, piece);
+
+                // hard drop
+                while tet.mov(&map, 0, 1) {}
+                map.apply(&tet, 0);
+            }
+        }
+    }
+
+    #[test]
+    fn sets_wider_than_the_board_are_rejected() {
+        let board = Board {
+            width: 4,
+            ..Board::default()
+        };
+
+        assert!(load_bundled("big.json").validate_board(&board).is_err());
+    }
+}
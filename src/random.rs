@@ -2,15 +2,14 @@ use rand::{
     rngs::StdRng,
     RngCore, SeedableRng,
 };
-use serde::Deserialize;
-
-use crate::tetrimino::TileType;
+use serde::{Serialize, Deserialize};
 
+/// Draws piece indices of the active piece set.
 pub trait RandomGenerator {
-    fn next(&mut self) -> TileType;
+    fn next(&mut self) -> usize;
 }
 
-#[derive(Copy, Clone, Deserialize)]
+#[derive(Copy, Clone, PartialEq, Serialize, Deserialize)]
 pub enum RandomGeneratorType {
     RandomBag,
     RandomNES,
@@ -18,79 +17,74 @@ pub enum RandomGeneratorType {
 
 pub struct RandomBag {
     rng: StdRng,
-    types: Vec<TileType>,
+    count: usize,
+    pieces: Vec<usize>,
 }
 
 impl RandomBag {
-    fn new(seed: [u8; 32]) -> RandomBag {
+    fn new(seed: [u8; 32], count: usize) -> RandomBag {
         RandomBag {
             rng: StdRng::from_seed(seed),
-            types: Vec::with_capacity(7),
+            count,
+            pieces: Vec::with_capacity(count),
         }
     }
 }
 
 impl RandomGenerator for RandomBag {
-    fn next(&mut self) -> TileType {
-        let mut len = self.types.len();
+    fn next(&mut self) -> usize {
+        let mut len = self.pieces.len();
 
         if len == 0 {
-            self.types.extend_from_slice(&[
-                TileType::I,
-                TileType::O,
-                TileType::T,
-                TileType::S,
-                TileType::Z,
-                TileType::J,
-                TileType::L,
-            ]);
-            len = 7;
+            self.pieces.extend(0..self.count);
+            len = self.count;
         }
 
         if len == 1 {
-            return self.types.pop().unwrap();
+            return self.pieces.pop().unwrap();
         }
 
         let value = (self.rng.next_u32() as usize) % len;
-        self.types.swap_remove(value)
+        self.pieces.swap_remove(value)
     }
 }
 
 struct RandomNES {
     rng: StdRng,
-    last: TileType,
+    count: usize,
+    last: Option<usize>,
 }
 
 impl RandomNES {
-    fn new(seed: [u8; 32]) -> RandomNES {
+    fn new(seed: [u8; 32], count: usize) -> RandomNES {
         RandomNES {
             rng: StdRng::from_seed(seed),
-            last: TileType::Empty,
+            count,
+            last: None,
         }
     }
 }
 
 impl RandomGenerator for RandomNES {
-    fn next(&mut self) -> TileType {
-        let value = (self.rng.next_u32() as usize) % 8;
-        let next = TileType::from_usize(value);
+    fn next(&mut self) -> usize {
+        // one more value than pieces (like the empty tile type on the NES), which triggers a reroll
+        let value = (self.rng.next_u32() as usize) % (self.count + 1);
 
-        if next == TileType::Empty || next == self.last {
-            let value = (self.rng.next_u32() as usize) % 7;
-            let next = TileType::from_usize(value);
+        if value == self.count || Some(value) == self.last {
+            let value = (self.rng.next_u32() as usize) % self.count;
 
-            self.last = next;
-            return next;
+            self.last = Some(value);
+            return value;
         }
 
-        self.last = next;
-        next
+        self.last = Some(value);
+        value
     }
 }
 
-pub fn create(seed: [u8; 32], t: RandomGeneratorType) -> Box<dyn RandomGenerator> {
+pub fn create(seed: [u8; 32], t: RandomGeneratorType, count: usize) -> Box<dyn RandomGenerator> {
     match t {
-        RandomGeneratorType::RandomBag => Box::new(RandomBag::new(seed)),
-        RandomGeneratorType::RandomNES => Box::new(RandomNES::new(seed)),
+        RandomGeneratorType::RandomBag => Box::new(RandomBag::new(seed, count)),
+        RandomGeneratorType::RandomNES => Box::new(RandomNES::new(seed, count)),
     }
 }
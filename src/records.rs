@@ -0,0 +1,47 @@
+use serde::{Serialize, Deserialize};
+use serde_json::Result;
+use std::io::{Read, Write};
+
+use crate::random::RandomGeneratorType;
+
+#[derive(Serialize, Deserialize)]
+struct SprintRecord {
+    random_generator: RandomGeneratorType,
+    lines: usize,
+    frames: usize,
+}
+
+/// Personal bests of the local player.
+#[derive(Default, Serialize, Deserialize)]
+pub struct Records {
+    sprint: Vec<SprintRecord>,
+}
+
+impl Records {
+    /// Returns whether the time is a new personal best.
+    pub fn submit_sprint(&mut self, random_generator: RandomGeneratorType, lines: usize, frames: usize) -> bool {
+        match self.sprint.iter_mut().find(|record| record.random_generator == random_generator && record.lines == lines) {
+            Some(record) if record.frames <= frames => false,
+            Some(record) => {
+                record.frames = frames;
+                true
+            },
+            None => {
+                self.sprint.push(SprintRecord {
+                    random_generator,
+                    lines,
+                    frames,
+                });
+                true
+            },
+        }
+    }
+
+    pub fn save<W: Write>(&self, writer: W) -> Result<()> {
+        serde_json::to_writer(writer, self)
+    }
+}
+
+pub fn load<R: Read>(reader: R) -> Result<Records> {
+    serde_json::from_reader(reader)
+}
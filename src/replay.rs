@@ -0,0 +1,113 @@
+use serde::{Serialize, Deserialize};
+use serde_json::Result;
+use std::io::{Read, Write};
+
+use crate::rules::{Rules, Handling};
+use crate::simulation::Input;
+
+/// A recorded session: everything needed to reproduce a `Simulation` frame by frame.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Replay {
+    pub seed: [u8; 32],
+    pub rules: Rules,
+    #[serde(default)]
+    pub handling: Handling,
+    inputs: Vec<Input>,
+    /// Garbage rows received before a frame (versus games), as `(frame, rows)` in frame order.
+    #[serde(default)]
+    garbage: Vec<(usize, usize)>,
+}
+
+impl Replay {
+    pub fn new(rules: &Rules, handling: &Handling, seed: [u8; 32]) -> Replay {
+        Replay {
+            seed,
+            rules: rules.clone(),
+            handling: handling.clone(),
+            inputs: Vec::new(),
+            garbage: Vec::new(),
+        }
+    }
+
+    /// Records the next frame and the garbage the player received before it.
+    pub fn record(&mut self, input: Input, garbage: usize) {
+        if garbage > 0 {
+            self.garbage.push((self.inputs.len(), garbage));
+        }
+        self.inputs.push(input);
+    }
+
+    pub fn input(&self, frame: usize) -> Option<Input> {
+        self.inputs.get(frame).copied()
+    }
+
+    pub fn garbage(&self, frame: usize) -> usize {
+        self.garbage.binary_search_by_key(&frame, |&(garbage_frame, _)| garbage_frame)
+            .map_or(0, |index| self.garbage[index].1)
+    }
+
+    pub fn len(&self) -> usize {
+        self.inputs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inputs.is_empty()
+    }
+
+    pub fn save<W: Write>(&self, writer: W) -> Result<()> {
+        serde_json::to_writer(writer, self)
+    }
+}
+
+pub fn load<R: Read>(reader: R) -> Result<Replay> {
+    serde_json::from_reader(reader)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+    use crate::simulation::{Simulation, Action};
+
+    #[test]
+    fn playback_reproduces_the_session() {
+        let settings: serde_json::Value = serde_json::from_str(include_str!("../resources/settings.json")).unwrap();
+        let mut rules: Rules = serde_json::from_value(settings["rules"].clone()).unwrap();
+        rules.hard_drop_enabled = true;
+        let handling = Handling::default();
+        let seed = [3; 32];
+
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut sim = Simulation::new(&rules, &handling, seed, true);
+        let mut recording = Replay::new(&rules, &handling, seed);
+        while !sim.is_over() && recording.len() < 10_000 {
+            let mut input = Input::default();
+            for &action in Action::ALL.iter() {
+                if rng.gen_bool(0.1) {
+                    input.set(action);
+                }
+            }
+            // as if an opponent attacked now and then
+            let garbage = if rng.gen_bool(0.01) { rng.gen_range(1, 4) } else { 0 };
+            recording.record(input, garbage);
+            sim.receive_garbage(garbage);
+            sim.step(&rules, input);
+        }
+
+        let mut file = Vec::new();
+        recording.save(&mut file).unwrap();
+        let replay = load(file.as_slice()).unwrap();
+
+        let mut playback = Simulation::new(&replay.rules, &replay.handling, replay.seed, true);
+        for frame in 0..replay.len() {
+            playback.receive_garbage(replay.garbage(frame));
+            playback.step(&replay.rules, replay.input(frame).unwrap());
+        }
+
+        assert!(sim.frame() > 0);
+        assert_eq!(playback.frame(), sim.frame());
+        assert_eq!(playback.score(), sim.score());
+        assert_eq!(playback.lines(), sim.lines());
+        assert!(playback.map() == sim.map());
+    }
+}
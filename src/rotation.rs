@@ -0,0 +1,564 @@
+use serde::{Serialize, Deserialize};
+
+use crate::tetrimino::{TileType, Tetrimino, Vec2i, Orientation, Mask, masks};
+use crate::map::Map;
+use crate::rules::{Rules, Board};
+use crate::piece::CustomRotation;
+
+// Spawn positions are defined for a standard board and moved to the center (and the hidden rows) of others.
+const STANDARD_WIDTH: i32 = 10;
+const STANDARD_HIDDEN_ROWS: i32 = 2;
+
+// All tables use screen coordinates (x right, y down) relative to the bounding box.
+// Orientations are indexed in clockwise order: [Deg0, Deg90, Deg180, Deg270].
+
+const NES_I: [Mask; 4] = masks([
+    [(0, 2), (1, 2), (2, 2), (3, 2)],
+    [(2, 0), (2, 1), (2, 2), (2, 3)],
+    [(0, 2), (1, 2), (2, 2), (3, 2)],
+    [(2, 0), (2, 1), (2, 2), (2, 3)],
+]);
+const NES_O: [Mask; 4] = masks([[(0, 1), (1, 1), (0, 2), (1, 2)]; 4]);
+const NES_T: [Mask; 4] = masks([
+    [(0, 1), (1, 1), (2, 1), (1, 2)],
+    [(1, 0), (0, 1), (1, 1), (1, 2)],
+    [(1, 0), (0, 1), (1, 1), (2, 1)],
+    [(1, 0), (1, 1), (2, 1), (1, 2)],
+]);
+const NES_S: [Mask; 4] = masks([
+    [(1, 1), (2, 1), (0, 2), (1, 2)],
+    [(1, 0), (1, 1), (2, 1), (2, 2)],
+    [(1, 1), (2, 1), (0, 2), (1, 2)],
+    [(1, 0), (1, 1), (2, 1), (2, 2)],
+]);
+const NES_Z: [Mask; 4] = masks([
+    [(0, 1), (1, 1), (1, 2), (2, 2)],
+    [(2, 0), (1, 1), (2, 1), (1, 2)],
+    [(0, 1), (1, 1), (1, 2), (2, 2)],
+    [(2, 0), (1, 1), (2, 1), (1, 2)],
+]);
+const NES_J: [Mask; 4] = masks([
+    [(0, 1), (1, 1), (2, 1), (2, 2)],
+    [(1, 0), (1, 1), (0, 2), (1, 2)],
+    [(0, 0), (0, 1), (1, 1), (2, 1)],
+    [(1, 0), (2, 0), (1, 1), (1, 2)],
+]);
+const NES_L: [Mask; 4] = masks([
+    [(0, 1), (1, 1), (2, 1), (0, 2)],
+    [(0, 0), (1, 0), (1, 1), (1, 2)],
+    [(2, 0), (0, 1), (1, 1), (2, 1)],
+    [(1, 0), (1, 1), (1, 2), (2, 2)],
+]);
+
+const SRS_I: [Mask; 4] = masks([
+    [(0, 1), (1, 1), (2, 1), (3, 1)],
+    [(2, 0), (2, 1), (2, 2), (2, 3)],
+    [(0, 2), (1, 2), (2, 2), (3, 2)],
+    [(1, 0), (1, 1), (1, 2), (1, 3)],
+]);
+const SRS_O: [Mask; 4] = masks([[(1, 0), (2, 0), (1, 1), (2, 1)]; 4]);
+const SRS_T: [Mask; 4] = masks([
+    [(1, 0), (0, 1), (1, 1), (2, 1)],
+    [(1, 0), (1, 1), (2, 1), (1, 2)],
+    [(0, 1), (1, 1), (2, 1), (1, 2)],
+    [(1, 0), (0, 1), (1, 1), (1, 2)],
+]);
+const SRS_S: [Mask; 4] = masks([
+    [(1, 0), (2, 0), (0, 1), (1, 1)],
+    [(1, 0), (1, 1), (2, 1), (2, 2)],
+    [(1, 1), (2, 1), (0, 2), (1, 2)],
+    [(0, 0), (0, 1), (1, 1), (1, 2)],
+]);
+const SRS_Z: [Mask; 4] = masks([
+    [(0, 0), (1, 0), (1, 1), (2, 1)],
+    [(2, 0), (1, 1), (2, 1), (1, 2)],
+    [(0, 1), (1, 1), (1, 2), (2, 2)],
+    [(1, 0), (0, 1), (1, 1), (0, 2)],
+]);
+const SRS_J: [Mask; 4] = masks([
+    [(0, 0), (0, 1), (1, 1), (2, 1)],
+    [(1, 0), (2, 0), (1, 1), (1, 2)],
+    [(0, 1), (1, 1), (2, 1), (2, 2)],
+    [(1, 0), (1, 1), (0, 2), (1, 2)],
+]);
+const SRS_L: [Mask; 4] = masks([
+    [(2, 0), (0, 1), (1, 1), (2, 1)],
+    [(1, 0), (1, 1), (1, 2), (2, 2)],
+    [(0, 1), (1, 1), (2, 1), (0, 2)],
+    [(0, 0), (1, 0), (1, 1), (1, 2)],
+]);
+
+// [clockwise, counterclockwise] by starting orientation
+const SRS_KICKS_JLSTZ: [[[(i32, i32); 5]; 4]; 2] = [
+    [
+        // Deg0 >> Deg90
+        [(0, 0), (-1, 0), (-1, -1), (0, 2), (-1, 2)],
+        // Deg90 >> Deg180
+        [(0, 0), ( 1, 0), ( 1,  1), (0,-2), ( 1,-2)],
+        // Deg180 >> Deg270
+        [(0, 0), ( 1, 0), ( 1, -1), (0, 2), ( 1, 2)],
+        // Deg270 >> Deg0
+        [(0, 0), (-1, 0), (-1,  1), (0,-2), (-1,-2)],
+    ],
+    [
+        // Deg0 >> Deg270
+        [(0, 0), ( 1, 0), ( 1, -1), (0, 2), ( 1, 2)],
+        // Deg90 >> Deg0
+        [(0, 0), ( 1, 0), ( 1,  1), (0,-2), ( 1,-2)],
+        // Deg180 >> Deg90
+        [(0, 0), (-1, 0), (-1, -1), (0, 2), (-1, 2)],
+        // Deg270 >> Deg180
+        [(0, 0), (-1, 0), (-1,  1), (0,-2), (-1,-2)],
+    ],
+];
+const SRS_KICKS_I: [[[(i32, i32); 5]; 4]; 2] = [
+    [
+        // Deg0 >> Deg90
+        [(0, 0), (-2, 0), ( 1, 0), (-2,  1), ( 1, -2)],
+        // Deg90 >> Deg180
+        [(0, 0), (-1, 0), ( 2, 0), (-1, -2), ( 2,  1)],
+        // Deg180 >> Deg270
+        [(0, 0), ( 2, 0), (-1, 0), ( 2, -1), (-1,  2)],
+        // Deg270 >> Deg0
+        [(0, 0), ( 1, 0), (-2, 0), ( 1,  2), (-2, -1)],
+    ],
+    [
+        // Deg0 >> Deg270
+        [(0, 0), (-1, 0), ( 2, 0), (-1, -2), ( 2,  1)],
+        // Deg90 >> Deg0
+        [(0, 0), ( 2, 0), (-1, 0), ( 2, -1), (-1,  2)],
+        // Deg180 >> Deg90
+        [(0, 0), ( 1, 0), (-2, 0), ( 1,  2), (-2, -1)],
+        // Deg270 >> Deg180
+        [(0, 0), (-2, 0), ( 1, 0), (-2,  1), ( 1, -2)],
+    ],
+];
+
+const ARS_I: [Mask; 4] = masks([
+    [(0, 1), (1, 1), (2, 1), (3, 1)],
+    [(2, 0), (2, 1), (2, 2), (2, 3)],
+    [(0, 1), (1, 1), (2, 1), (3, 1)],
+    [(2, 0), (2, 1), (2, 2), (2, 3)],
+]);
+const ARS_O: [Mask; 4] = masks([[(1, 1), (2, 1), (1, 2), (2, 2)]; 4]);
+const ARS_T: [Mask; 4] = masks([
+    [(0, 1), (1, 1), (2, 1), (1, 2)],
+    [(1, 0), (0, 1), (1, 1), (1, 2)],
+    [(1, 1), (0, 2), (1, 2), (2, 2)],
+    [(1, 0), (1, 1), (2, 1), (1, 2)],
+]);
+const ARS_S: [Mask; 4] = masks([
+    [(1, 1), (2, 1), (0, 2), (1, 2)],
+    [(0, 0), (0, 1), (1, 1), (1, 2)],
+    [(1, 1), (2, 1), (0, 2), (1, 2)],
+    [(0, 0), (0, 1), (1, 1), (1, 2)],
+]);
+const ARS_Z: [Mask; 4] = masks([
+    [(0, 1), (1, 1), (1, 2), (2, 2)],
+    [(2, 0), (1, 1), (2, 1), (1, 2)],
+    [(0, 1), (1, 1), (1, 2), (2, 2)],
+    [(2, 0), (1, 1), (2, 1), (1, 2)],
+]);
+const ARS_J: [Mask; 4] = masks([
+    [(0, 1), (1, 1), (2, 1), (2, 2)],
+    [(1, 0), (1, 1), (0, 2), (1, 2)],
+    [(0, 1), (0, 2), (1, 2), (2, 2)],
+    [(1, 0), (2, 0), (1, 1), (1, 2)],
+]);
+const ARS_L: [Mask; 4] = masks([
+    [(0, 1), (1, 1), (2, 1), (0, 2)],
+    [(0, 0), (1, 0), (1, 1), (1, 2)],
+    [(2, 1), (0, 2), (1, 2), (2, 2)],
+    [(1, 0), (1, 1), (1, 2), (2, 2)],
+]);
+
+const NO_KICKS: [(i32, i32); 1] = [(0, 0)];
+const ARS_KICKS: [(i32, i32); 3] = [(0, 0), (1, 0), (-1, 0)];
+
+#[derive(Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum RotationSystemType {
+    RotationNES,
+    RotationSRS,
+    RotationARS,
+}
+
+/// Shapes, spawn positions and kicks of a piece set (pieces are identified by their index).
+pub trait RotationSystem {
+    /// Number of pieces (the standard set has the seven tetriminos in `TileType` order).
+    fn piece_count(&self) -> usize {
+        7
+    }
+
+    /// Tile sprite of a piece.
+    fn tile_type(&self, piece: usize) -> TileType {
+        TileType::from_usize(piece)
+    }
+
+    /// Cell mask of a piece in the given orientation (relative to its bounding box).
+    fn mask(&self, piece: usize, orientation: Orientation) -> Mask;
+
+    /// Position of the bounding box at spawn (on a standard board).
+    fn spawn_position(&self, piece: usize) -> (i32, i32);
+
+    /// Offsets which are tested in order when rotating out of `from` (the first one which fits wins).
+    fn kicks(&self, piece: usize, from: Orientation, clockwise: bool) -> &[(i32, i32)];
+
+    fn spawn(&self, piece: usize, board: &Board) -> Tetrimino {
+        let (x, y) = self.spawn_position(piece);
+        let x = x + (board.width as i32 - STANDARD_WIDTH) / 2;
+        let y = y + board.hidden_rows as i32 - STANDARD_HIDDEN_ROWS;
+        Tetrimino::new(piece, self.tile_type(piece), Vec2i::new(x, y), Orientation::Deg0, self.mask(piece, Orientation::Deg0))
+    }
+
+    fn rotate(&self, tet: &Tetrimino, map: &Map, clockwise: bool) -> Option<Tetrimino> {
+        let rotated = self.rotated(tet, clockwise);
+        kick(rotated, map, self.kicks(tet.piece, tet.orientation, clockwise))
+    }
+
+    /// Rotated tetrimino at the same position (might collide).
+    fn rotated(&self, tet: &Tetrimino, clockwise: bool) -> Tetrimino {
+        let orientation = tet.orientation.rotate(clockwise);
+        Tetrimino::new(tet.piece, tet.tile_type, tet.pos, orientation, self.mask(tet.piece, orientation))
+    }
+}
+
+pub fn kick(mut tet: Tetrimino, map: &Map, kicks: &[(i32, i32)]) -> Option<Tetrimino> {
+    for &(x, y) in kicks.iter() {
+        if tet.mov(map, x, y) {
+            return Some(tet);
+        }
+    }
+
+    None
+}
+
+/// Nintendo Entertainment System: flat side up spawn orientations, no kicks.
+pub struct RotationNES;
+
+impl RotationSystem for RotationNES {
+    fn mask(&self, piece: usize, orientation: Orientation) -> Mask {
+        let masks = match TileType::from_usize(piece) {
+            TileType::I => &NES_I,
+            TileType::O => &NES_O,
+            TileType::T => &NES_T,
+            TileType::S => &NES_S,
+            TileType::Z => &NES_Z,
+            TileType::J => &NES_J,
+            TileType::L => &NES_L,
+            TileType::Empty | TileType::Garbage => panic!("dead code"),
+        };
+
+        masks[orientation as usize]
+    }
+
+    fn spawn_position(&self, piece: usize) -> (i32, i32) {
+        // pivot at column 5 in the first visible row
+        match TileType::from_usize(piece) {
+            TileType::I => (3, 0),
+            _ => (4, 1),
+        }
+    }
+
+    fn kicks(&self, _piece: usize, _from: Orientation, _clockwise: bool) -> &[(i32, i32)] {
+        &NO_KICKS
+    }
+}
+
+/// Guideline Super Rotation System.
+pub struct RotationSRS;
+
+impl RotationSystem for RotationSRS {
+    fn mask(&self, piece: usize, orientation: Orientation) -> Mask {
+        let masks = match TileType::from_usize(piece) {
+            TileType::I => &SRS_I,
+            TileType::O => &SRS_O,
+            TileType::T => &SRS_T,
+            TileType::S => &SRS_S,
+            TileType::Z => &SRS_Z,
+            TileType::J => &SRS_J,
+            TileType::L => &SRS_L,
+            TileType::Empty | TileType::Garbage => panic!("dead code"),
+        };
+
+        masks[orientation as usize]
+    }
+
+    fn spawn_position(&self, _piece: usize) -> (i32, i32) {
+        // above the visible field
+        (3, 0)
+    }
+
+    fn kicks(&self, piece: usize, from: Orientation, clockwise: bool) -> &[(i32, i32)] {
+        let direction = if clockwise { 0 } else { 1 };
+
+        match TileType::from_usize(piece) {
+            TileType::I => &SRS_KICKS_I[direction][from as usize],
+            TileType::O => &NO_KICKS,
+            _ => &SRS_KICKS_JLSTZ[direction][from as usize],
+        }
+    }
+}
+
+/// Arika Rotation System (TGM): bottom aligned rotation states, simple sideways kicks.
+pub struct RotationARS;
+
+impl RotationARS {
+    /// Center column rule: J, L and T do not kick if the first blocked cell
+    /// (in reading order of the bounding box) lies in the center column.
+    fn center_column_blocked(tet: &Tetrimino, map: &Map) -> bool {
+        // mask bits are already in reading order
+        let blocked = tet.tiles().find(|&(x, y)| map.occupied(tet.pos.x + x, tet.pos.y + y));
+        matches!(blocked, Some((1, _)))
+    }
+}
+
+impl RotationSystem for RotationARS {
+    fn mask(&self, piece: usize, orientation: Orientation) -> Mask {
+        let masks = match TileType::from_usize(piece) {
+            TileType::I => &ARS_I,
+            TileType::O => &ARS_O,
+            TileType::T => &ARS_T,
+            TileType::S => &ARS_S,
+            TileType::Z => &ARS_Z,
+            TileType::J => &ARS_J,
+            TileType::L => &ARS_L,
+            TileType::Empty | TileType::Garbage => panic!("dead code"),
+        };
+
+        masks[orientation as usize]
+    }
+
+    fn spawn_position(&self, _piece: usize) -> (i32, i32) {
+        // first visible row
+        (3, 1)
+    }
+
+    fn kicks(&self, piece: usize, _from: Orientation, _clockwise: bool) -> &[(i32, i32)] {
+        match TileType::from_usize(piece) {
+            TileType::I | TileType::O => &NO_KICKS,
+            _ => &ARS_KICKS,
+        }
+    }
+
+    fn rotate(&self, tet: &Tetrimino, map: &Map, clockwise: bool) -> Option<Tetrimino> {
+        let rotated = self.rotated(tet, clockwise);
+
+        if !map.collision(&rotated) {
+            return Some(rotated);
+        }
+
+        match tet.tile_type {
+            TileType::J | TileType::L | TileType::T if RotationARS::center_column_blocked(&rotated, map) => None,
+            _ => kick(rotated, map, self.kicks(tet.piece, tet.orientation, clockwise)),
+        }
+    }
+}
+
+/// Rotation system of the rules (the custom piece set if there is one).
+pub fn from_rules(rules: &Rules) -> Box<dyn RotationSystem> {
+    match &rules.piece_set {
+        Some(piece_set) => Box::new(CustomRotation::new(piece_set)),
+        None => create(rules.rotation_system),
+    }
+}
+
+pub fn create(t: RotationSystemType) -> Box<dyn RotationSystem> {
+    match t {
+        RotationSystemType::RotationNES => Box::new(RotationNES),
+        RotationSystemType::RotationSRS => Box::new(RotationSRS),
+        RotationSystemType::RotationARS => Box::new(RotationARS),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct KickCase {
+        name: &'static str,
+        system: RotationSystemType,
+        tile_type: TileType,
+        pos: (i32, i32),
+        orientation: Orientation,
+        clockwise: bool,
+        blocks: &'static [(usize, usize)],
+        expected: Option<((i32, i32), Orientation)>,
+    }
+
+    const CASES: &[KickCase] = &[
+        KickCase {
+            name: "SRS T rotates in place on an empty map",
+            system: RotationSystemType::RotationSRS,
+            tile_type: TileType::T,
+            pos: (3, 10),
+            orientation: Orientation::Deg0,
+            clockwise: true,
+            blocks: &[],
+            expected: Some(((3, 10), Orientation::Deg90)),
+        },
+        KickCase {
+            name: "SRS T kicks off the left wall (test 2)",
+            system: RotationSystemType::RotationSRS,
+            tile_type: TileType::T,
+            pos: (-1, 10),
+            orientation: Orientation::Deg90,
+            clockwise: true,
+            blocks: &[],
+            expected: Some(((0, 10), Orientation::Deg180)),
+        },
+        KickCase {
+            name: "SRS T kicks up and left (test 3)",
+            system: RotationSystemType::RotationSRS,
+            tile_type: TileType::T,
+            pos: (4, 18),
+            orientation: Orientation::Deg0,
+            clockwise: true,
+            blocks: &[(5, 20), (4, 20)],
+            expected: Some(((3, 17), Orientation::Deg90)),
+        },
+        KickCase {
+            name: "SRS T kicks two rows down and left (test 5)",
+            system: RotationSystemType::RotationSRS,
+            tile_type: TileType::T,
+            pos: (4, 10),
+            orientation: Orientation::Deg0,
+            clockwise: true,
+            blocks: &[(5, 12), (4, 10)],
+            expected: Some(((3, 12), Orientation::Deg90)),
+        },
+        KickCase {
+            name: "SRS I kicks off the floor (test 5)",
+            system: RotationSystemType::RotationSRS,
+            tile_type: TileType::I,
+            pos: (3, 20),
+            orientation: Orientation::Deg0,
+            clockwise: true,
+            blocks: &[],
+            expected: Some(((4, 18), Orientation::Deg90)),
+        },
+        KickCase {
+            name: "SRS I kicks off the right wall (test 3)",
+            system: RotationSystemType::RotationSRS,
+            tile_type: TileType::I,
+            pos: (8, 10),
+            orientation: Orientation::Deg270,
+            clockwise: true,
+            blocks: &[],
+            expected: Some(((6, 10), Orientation::Deg0)),
+        },
+        KickCase {
+            name: "SRS O never moves",
+            system: RotationSystemType::RotationSRS,
+            tile_type: TileType::O,
+            pos: (3, 10),
+            orientation: Orientation::Deg0,
+            clockwise: false,
+            blocks: &[],
+            expected: Some(((3, 10), Orientation::Deg270)),
+        },
+        KickCase {
+            name: "NES T does not kick off the wall",
+            system: RotationSystemType::RotationNES,
+            tile_type: TileType::T,
+            pos: (-1, 10),
+            orientation: Orientation::Deg270,
+            clockwise: true,
+            blocks: &[],
+            expected: None,
+        },
+        KickCase {
+            name: "NES I rotates around its pivot",
+            system: RotationSystemType::RotationNES,
+            tile_type: TileType::I,
+            pos: (3, 10),
+            orientation: Orientation::Deg0,
+            clockwise: true,
+            blocks: &[],
+            expected: Some(((3, 10), Orientation::Deg90)),
+        },
+        KickCase {
+            name: "ARS T kicks right off the left wall",
+            system: RotationSystemType::RotationARS,
+            tile_type: TileType::T,
+            pos: (-1, 10),
+            orientation: Orientation::Deg270,
+            clockwise: true,
+            blocks: &[],
+            expected: Some(((0, 10), Orientation::Deg0)),
+        },
+        KickCase {
+            name: "ARS T kicks left if right is blocked",
+            system: RotationSystemType::RotationARS,
+            tile_type: TileType::T,
+            pos: (8, 10),
+            orientation: Orientation::Deg90,
+            clockwise: true,
+            blocks: &[],
+            expected: Some(((7, 10), Orientation::Deg180)),
+        },
+        KickCase {
+            name: "ARS T does not kick if blocked in the center column",
+            system: RotationSystemType::RotationARS,
+            tile_type: TileType::T,
+            pos: (4, 10),
+            orientation: Orientation::Deg0,
+            clockwise: true,
+            blocks: &[(5, 10)],
+            expected: None,
+        },
+        KickCase {
+            name: "ARS I does not kick",
+            system: RotationSystemType::RotationARS,
+            tile_type: TileType::I,
+            pos: (3, 19),
+            orientation: Orientation::Deg0,
+            clockwise: true,
+            blocks: &[],
+            expected: None,
+        },
+    ];
+
+    #[test]
+    fn kick_tables() {
+        for case in CASES.iter() {
+            let system = create(case.system);
+
+            let mut map = Map::new(&Board::default());
+            for &(x, y) in case.blocks.iter() {
+                map.set(x, y, TileType::O);
+            }
+
+            let pos = Vec2i::new(case.pos.0, case.pos.1);
+            let piece = case.tile_type as usize;
+            let tet = Tetrimino::new(piece, case.tile_type, pos, case.orientation, system.mask(piece, case.orientation));
+            assert!(!map.collision(&tet), "{}: start position collides", case.name);
+
+            let result = system
+                .rotate(&tet, &map, case.clockwise)
+                .map(|tet| ((tet.pos.x, tet.pos.y), tet.orientation));
+            assert_eq!(result, case.expected, "{}", case.name);
+        }
+    }
+
+    #[test]
+    fn four_rotations_return_to_start() {
+        let types = [TileType::I, TileType::O, TileType::T, TileType::S, TileType::Z, TileType::J, TileType::L];
+
+        for &system_type in [RotationSystemType::RotationNES, RotationSystemType::RotationSRS, RotationSystemType::RotationARS].iter() {
+            let system = create(system_type);
+            let board = Board::default();
+            let map = Map::new(&board);
+
+            for &tile_type in types.iter() {
+                let spawn = system.spawn(tile_type as usize, &board);
+                let mut tet = system.spawn(tile_type as usize, &board);
+                for _ in 0..4 {
+                    tet = system.rotate(&tet, &map, true).unwrap();
+                }
+
+                assert_eq!(tet.orientation, spawn.orientation);
+                assert_eq!((tet.pos.x, tet.pos.y), (spawn.pos.x, spawn.pos.y));
+            }
+        }
+    }
+}
@@ -0,0 +1,218 @@
+use serde::{Serialize, Deserialize};
+use std::cmp;
+
+use crate::random::RandomGeneratorType;
+use crate::rotation::RotationSystemType;
+use crate::piece::PieceSet;
+
+/// Narrowest board an I tetrimino fits into.
+pub const BOARD_WIDTH_MIN: usize = 4;
+pub const BOARD_VISIBLE_HEIGHT_MIN: usize = 4;
+/// Tetriminos spawn (partly) within the hidden rows.
+pub const BOARD_HIDDEN_ROWS_MIN: usize = 2;
+
+/// Highest selectable start level (the NES kill screen).
+pub const START_LEVEL_MAX: usize = 29;
+
+pub const PREVIEW_COUNT_MIN: usize = 1;
+pub const PREVIEW_COUNT_MAX: usize = 6;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Rules {
+    pub mode: GameMode,
+    board: Board,
+    pub random_generator: RandomGeneratorType,
+    pub start_level: usize,
+    pub rotation_system: RotationSystemType,
+    pub hard_drop_enabled: bool,
+    pub ghost_enabled: bool,
+    pub stack_visibility: StackVisibility,
+    /// NES entry delay (depends on the lock height) and line clear delay (depends on the frame counter).
+    pub classic_timing: bool,
+    /// When the game is over (replays recorded before top out rules existed use the classic one).
+    #[serde(default)]
+    pub top_out: TopOut,
+    /// Garbage sent by line clears in versus games.
+    #[serde(default)]
+    pub attack: AttackTable,
+    preview_count: usize,
+    /// Custom pieces (loaded from `Settings::piece_set_file`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub piece_set: Option<PieceSet>,
+}
+
+/// Board dimensions (in tiles).
+#[derive(Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Board {
+    pub width: usize,
+    pub visible_height: usize,
+    /// Rows above the visible field.
+    pub hidden_rows: usize,
+}
+
+impl Board {
+    pub fn height(&self) -> usize {
+        self.visible_height + self.hidden_rows
+    }
+}
+
+impl Default for Board {
+    fn default() -> Board {
+        Board {
+            width: 10,
+            visible_height: 20,
+            hidden_rows: 2,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum GameMode {
+    /// Endless, until the stack reaches the top.
+    Marathon,
+    /// Clear the line target as fast as possible.
+    Sprint { lines: usize },
+    /// Score as much as possible before the time (in frames) runs out.
+    Ultra { frames: usize },
+    /// Clear all garbage rows as fast as possible.
+    Dig { rows: usize, hole_change_probability: f32 },
+}
+
+/// How long locked tiles stay visible.
+#[derive(Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum StackVisibility {
+    Visible,
+    Invisible,
+    /// Tiles fade out over the given number of frames.
+    Fading { frames: usize },
+}
+
+impl StackVisibility {
+    /// Alpha value of a tile which was locked `age` frames ago.
+    pub fn alpha(&self, age: usize) -> f32 {
+        match *self {
+            StackVisibility::Visible => 1.0,
+            StackVisibility::Invisible => 0.0,
+            StackVisibility::Fading { frames } => 1.0 - (age as f32 / cmp::max(frames, 1) as f32).min(1.0),
+        }
+    }
+}
+
+/// Conditions which end the game when the stack reaches the top.
+///
+/// Tiles in the hidden rows stay part of the stack in any case (vanish zone).
+#[derive(Copy, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub enum TopOut {
+    /// NES: the next tetrimino overlaps the stack right after a lock (before lines are cleared).
+    #[default]
+    Classic,
+    /// Block out: a tetrimino overlaps the stack when it spawns.
+    BlockOut,
+    /// Block out, or lock out: a tetrimino locks entirely within the hidden rows.
+    Guideline,
+}
+
+/// Garbage rows a line clear sends to the opponent.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct AttackTable {
+    /// Rows sent by a single, double, triple and tetris (clears of more lines send the last value).
+    pub clears: Vec<usize>,
+    /// Rows added by the 1st, 2nd, ... clear in a row after the first one (the last value repeats).
+    pub combo: Vec<usize>,
+    /// Probability that a garbage row has its hole in another column than the row below.
+    pub hole_change_probability: f32,
+}
+
+impl AttackTable {
+    /// Rows sent by clearing `lines` lines (`combo` counts the previous clears in a row).
+    pub fn rows(&self, lines: usize, combo: usize) -> usize {
+        fn value(values: &[usize], index: usize) -> usize {
+            values.get(index).or_else(|| values.last()).copied().unwrap_or(0)
+        }
+
+        if lines == 0 {
+            return 0;
+        }
+
+        let bonus = if combo == 0 { 0 } else { value(&self.combo, combo - 1) };
+        value(&self.clears, lines - 1) + bonus
+    }
+}
+
+impl Default for AttackTable {
+    fn default() -> AttackTable {
+        AttackTable {
+            clears: vec![0, 1, 2, 4],
+            combo: vec![1, 1, 2, 2, 3, 3, 4, 4, 4, 5],
+            hole_change_probability: 0.3,
+        }
+    }
+}
+
+/// What happens to the auto shift charge when the tetrimino is blocked by a wall or the stack.
+#[derive(Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum DasCharge {
+    /// NES: the full delay has to pass again before the next auto shift.
+    ResetOnWall,
+    /// Modern games: the charge is kept, so the tetrimino moves as soon as it can.
+    Preserve,
+}
+
+/// Input handling of a player (part of the profile, not of the game rules).
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Handling {
+    /// Delayed auto shift: frames without a shift between the first and the second shift.
+    pub das: usize,
+    /// Auto repeat rate: frames without a shift between further shifts (0 = instantly to the wall).
+    pub arr: usize,
+    /// Gravity multiplier while soft dropping.
+    pub soft_drop_factor: usize,
+    pub das_charge: DasCharge,
+}
+
+impl Default for Handling {
+    fn default() -> Handling {
+        Handling {
+            das: 16,
+            arr: 6,
+            soft_drop_factor: 2,
+            das_charge: DasCharge::ResetOnWall,
+        }
+    }
+}
+
+impl Rules {
+    pub fn board(&self) -> Board {
+        Board {
+            width: cmp::max(self.board.width, BOARD_WIDTH_MIN),
+            visible_height: cmp::max(self.board.visible_height, BOARD_VISIBLE_HEIGHT_MIN),
+            hidden_rows: cmp::max(self.board.hidden_rows, BOARD_HIDDEN_ROWS_MIN),
+        }
+    }
+
+    pub fn preview_count(&self) -> usize {
+        self.preview_count.clamp(PREVIEW_COUNT_MIN, PREVIEW_COUNT_MAX)
+    }
+
+    pub fn set_preview_count(&mut self, preview_count: usize) {
+        self.preview_count = preview_count.clamp(PREVIEW_COUNT_MIN, PREVIEW_COUNT_MAX);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attack_rows_repeat_the_last_value() {
+        let table = AttackTable::default();
+
+        assert_eq!(table.rows(0, 5), 0);
+        assert_eq!(table.rows(1, 0), 0);
+        assert_eq!(table.rows(4, 0), 4);
+        // third clear in a row
+        assert_eq!(table.rows(2, 2), 1 + 1);
+        // larger pieces and long combos
+        assert_eq!(table.rows(5, 20), 4 + 5);
+    }
+}
@@ -1,24 +1,29 @@
-use serde::Deserialize;
+use serde::{
+    de::Error,
+    Serialize, Deserialize,
+};
 use serde_json::Result;
-use std::io::Read;
-use crate::random::RandomGeneratorType;
+use std::{
+    io::{self, Read, Write},
+    fs::{self, File},
+    path::{Path, PathBuf},
+    env,
+};
+use crate::rules::{Rules, Handling, START_LEVEL_MAX};
+use crate::keymap::{self, Keymap};
 use crate::engine::{
     graphics::{Rect, Color},
     vec::Vec2f,
 };
 
-pub const MAP_WIDTH: usize = 10;
-pub const MAP_HEIGHT: usize = 22;
-pub const MAP_TILE_COUNT: usize = MAP_WIDTH * MAP_HEIGHT;
-
-#[derive(Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct SoundSettings {
 	pub file: String,
 	pub enabled: bool,
 	pub volume: f32,
 }
 
-#[derive(Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct BackgroundSettings {
 	pub file: String,
 	pub w: f32,
@@ -27,36 +32,53 @@ pub struct BackgroundSettings {
     pub gray_color: Color,
 }
 
-#[derive(Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct PopupSettings {
 	pub file: String,
 	pub bounds: Rect,
 }
 
-#[derive(Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct TileSettings {
 	pub file: String,
 	pub size: f32,
+	pub ghost_color: Color,
+	pub garbage_color: Color,
+	/// Outline of freshly locked tiles of fading stacks.
+	pub lock_flash_color: Color,
+	/// Pending garbage next to the map (versus games).
+	pub garbage_meter_color: Color,
 }
 
-#[derive(Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct FontSettings {
 	pub file: String,
 	pub next_text_y_offset: f32,
 	pub size_default: f32,
 	pub size_player: f32,
     pub size_popup: f32,
+    pub size_stats: f32,
+    pub size_menu: f32,
     pub color: Color,
 }
 
-#[derive(Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Settings {
-    pub random_generator: RandomGeneratorType,
-    pub start_level: usize,
-    pub wall_kicks_enabled: bool,
-    pub hard_drop_enabled: bool,
+    pub rules: Rules,
+    /// Piece set (relative to the resources) which replaces the tetriminos.
+    pub piece_set_file: Option<String>,
+    pub handling: Handling,
+    /// Keys of the local players (the second one is used in split screen).
+    pub keymaps: [Keymap; 2],
+
+    pub replay_directory: String,
+    pub personal_best_file: String,
+    pub leaderboard_file: String,
+    pub stats_directory: String,
 
     pub nickname: String,
+    /// Nickname of the second local player (split screen).
+    pub second_nickname: String,
     pub connection: String,
     pub multiplayer_enabled: bool,
     
@@ -72,10 +94,53 @@ pub struct Settings {
     pub lines_bounds: [Rect; 2],
     pub level_bounds: [Rect; 2],
     pub next_bounds: [Rect; 2],
+    pub hold_bounds: [Rect; 2],
+    /// Piece counts and rates next to the background (singleplayer only).
+    pub stats_panel_enabled: bool,
+    pub stats_bounds: Rect,
+    /// Shows the optimal keys after a finesse fault.
+    pub finesse_trainer_enabled: bool,
     pub map_positions: [Vec2f; 2],
+    /// Area of the map on the background (board dimensions other than the default are fit into it).
+    pub map_size: Vec2f,
 }
 
 impl Settings {
+    /// Checks the values which can be edited in the game.
+    fn validate(&self) -> std::result::Result<(), String> {
+        if self.rules.start_level > START_LEVEL_MAX {
+            return Err(format!("start level {} (0 to {})", self.rules.start_level, START_LEVEL_MAX));
+        }
+
+        if !(0.0..=1.0).contains(&self.sound.volume) {
+            return Err(format!("sound volume {} (0 to 1)", self.sound.volume));
+        }
+
+        if self.handling.soft_drop_factor == 0 {
+            return Err("soft drop factor 0 (at least 1)".to_string());
+        }
+
+        if let Some(key) = keymap::find_conflict(&self.keymaps) {
+            return Err(format!("key {:?} is bound more than once or reserved", key));
+        }
+
+        if let Some(piece_set) = &self.rules.piece_set {
+            piece_set.validate_board(&self.rules.board())?;
+        }
+
+        Ok(())
+    }
+
+    pub fn save<W: Write>(&self, writer: W) -> Result<()> {
+        self.validate().map_err(serde_json::Error::custom)?;
+
+        // the piece set is loaded from `piece_set_file`
+        let mut settings = self.clone();
+        settings.rules.piece_set = None;
+
+        serde_json::to_writer_pretty(writer, &settings)
+    }
+
     pub fn background(&self) -> &BackgroundSettings {
         if self.multiplayer_enabled {
             &self.multiplayer
@@ -83,8 +148,44 @@ impl Settings {
             &self.singleplayer
         }
     }
+
+    /// Width of the window (extended by the stats panel).
+    pub fn window_width(&self) -> f32 {
+        let width = self.background().w;
+
+        if self.stats_panel_enabled && !self.multiplayer_enabled {
+            width.max(self.stats_bounds.x + self.stats_bounds.w)
+        } else {
+            width
+        }
+    }
+}
+
+/// Settings file (relative to the working directory, or to the crate when run with cargo).
+pub const SETTINGS_FILE: &str = "resources/settings.json";
+
+/// Resolves a path for files written by the game (replays, ...).
+pub fn user_data_path(path: &str) -> PathBuf {
+    if let Ok(manifest_dir) = env::var("CARGO_MANIFEST_DIR") {
+        PathBuf::from(manifest_dir + "/" + path)
+    } else {
+        PathBuf::from(path)
+    }
+}
+
+/// Writes a file through a temporary file next to it, so that it is never left half written.
+pub fn write_atomic(path: &Path, write: impl FnOnce(&mut File) -> io::Result<()>) -> io::Result<()> {
+    let temp_path = path.with_extension("tmp");
+
+    let mut file = File::create(&temp_path)?;
+    write(&mut file)?;
+    file.sync_all()?;
+
+    fs::rename(&temp_path, path)
 }
 
 pub fn load<R: Read>(reader: R) -> Result<Settings> {
-    serde_json::from_reader(reader)
+    let settings: Settings = serde_json::from_reader(reader)?;
+    settings.validate().map_err(serde_json::Error::custom)?;
+    Ok(settings)
 }
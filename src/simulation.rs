@@ -0,0 +1,930 @@
+use serde::{Serialize, Deserialize};
+use std::{
+    cmp, mem,
+    collections::VecDeque,
+};
+
+use crate::tetrimino::{TileType, Tetrimino};
+use crate::rules::{Rules, Board, Handling, DasCharge, GameMode, TopOut};
+use crate::random::{self, RandomGenerator};
+use crate::rotation::{self, RotationSystem};
+use crate::map::{Map, CompleteLines};
+use crate::garbage::{self, GarbageGenerator};
+use crate::stats::Statistics;
+use crate::finesse::{self, FinesseKey, FinesseTracker};
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum Action {
+    RotateLeft,
+    RotateRight,
+    MoveLeft,
+    MoveRight,
+    SoftDrop,
+    HardDrop,
+    Hold,
+    Drop,
+}
+
+impl Action {
+    pub const ALL: [Action; 8] = [
+        Action::RotateLeft,
+        Action::RotateRight,
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::SoftDrop,
+        Action::HardDrop,
+        Action::Hold,
+        Action::Drop,
+    ];
+}
+
+/// Per-frame input bitmask (one bit per `Action`).
+#[derive(Copy, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct Input(u8);
+
+impl Input {
+    pub fn set(&mut self, action: Action) {
+        self.0 |= 1 << action as u8;
+    }
+
+    pub fn contains(self, action: Action) -> bool {
+        self.0 & (1 << action as u8) != 0
+    }
+}
+
+pub enum Event {
+    Action(Action),
+    Spawn,
+    /// More keys than necessary were used to place the last piece (optimal keys).
+    FinesseFault(Vec<FinesseKey>),
+    /// Garbage rows sent to the opponent (what is left after cancelling the pending garbage).
+    Attack(usize),
+}
+
+/// Spawn delay (ARE) after a lock without line clears.
+const ENTRY_DELAY: usize = 10;
+/// Duration of the line clear animation.
+const LINE_CLEAR_DELAY: usize = 20;
+/// The animation advances every 4 frames.
+const LINE_CLEAR_STEPS: usize = LINE_CLEAR_DELAY / 4;
+
+/// NES entry delay: 10 frames when locking in the bottom two rows, 2 more for every 4 rows above.
+pub fn classic_entry_delay(lock_height: usize) -> usize {
+    cmp::min(10 + (lock_height + 2) / 4 * 2, 18)
+}
+
+/// NES line clear delay: the animation advances every 4th frame of the global frame counter (17 to 20 frames).
+pub fn classic_line_clear_delay(frame: usize) -> usize {
+    LINE_CLEAR_DELAY - frame % 4
+}
+
+pub fn gravity_value(level: usize) -> usize {
+    match level {
+        0 => 48,
+        1 => 43,
+        2 => 38,
+        3 => 33,
+        4 => 28,
+        5 => 23,
+        6 => 18,
+        7 => 13,
+        8 => 8,
+        9 => 6,
+        10 => 5,
+        11 => 5,
+        12 => 5,
+        13 => 4,
+        14 => 4,
+        15 => 4,
+        16 => 3,
+        17 => 3,
+        18 => 3,
+        19 => 2,
+        20 => 2,
+        21 => 2,
+        22 => 2,
+        23 => 2,
+        24 => 2,
+        25 => 2,
+        26 => 2,
+        27 => 2,
+        28 => 2,
+        _ => 1,
+    }
+}
+
+/// Gameplay of a single board, advanced one 60 Hz frame at a time.
+///
+/// Does not depend on the engine, so it can run without a window (tests, servers, bots).
+pub struct Simulation {
+    gen: Box<dyn RandomGenerator>,
+    rotation: Box<dyn RotationSystem>,
+    auto_drop: bool,
+    handling: Handling,
+
+    board: Board,
+    map: Map,
+    current: Tetrimino,
+    next: VecDeque<Tetrimino>,
+    hold: Option<Tetrimino>,
+
+    score: usize,
+    lines: usize,
+    level: usize,
+
+    line_counter: isize,
+    stats: Statistics,
+    finesse: FinesseTracker,
+
+    /// Clears in a row after the first one (`None` after a lock without clears).
+    combo: Option<usize>,
+    /// Garbage rows received, inserted at the next lock without clears.
+    pending_garbage: usize,
+    garbage: GarbageGenerator,
+
+    frame: usize,
+    entry_delay: usize,
+
+    drop_timer: Option<usize>,
+    spawn_delay_timer: Option<usize>,
+    animation_timer: Option<usize>,
+
+    soft_drop: bool,
+    animation_info: CompleteLines,
+
+    left_timer: Option<usize>,
+    right_timer: Option<usize>,
+
+    hold_used: bool,
+    gameover: bool,
+    finished: bool,
+
+    events: Vec<Event>,
+}
+
+impl Simulation {
+    pub fn new(rules: &Rules, handling: &Handling, seed: [u8; 32], auto_drop: bool) -> Simulation {
+        let rotation = rotation::from_rules(rules);
+        let mut gen = random::create(seed, rules.random_generator, rotation.piece_count());
+        let board = rules.board();
+        let current = rotation.spawn(gen.next(), &board);
+        let next = Simulation::next_queue(rules, gen.as_mut(), rotation.as_ref());
+        let map = Simulation::initial_map(rules, seed);
+        // with any top out rule (a piece set too wide for the board spawns outside of it)
+        let gameover = map.collision(&current);
+
+        let start_level = rules.start_level;
+        let line_counter = Simulation::line_counter(start_level);
+
+        let mut stats = Statistics::default();
+        stats.on_spawn(current.tile_type);
+
+        Simulation {
+            gen,
+            rotation,
+            auto_drop,
+            handling: handling.clone(),
+
+            board,
+            map,
+            current,
+            next,
+            hold: None,
+
+            score: 0,
+            lines: 0,
+            level: start_level,
+
+            line_counter,
+            stats,
+            finesse: FinesseTracker::default(),
+
+            combo: None,
+            pending_garbage: 0,
+            garbage: GarbageGenerator::new(garbage::seed(seed), board.width, rules.attack.hole_change_probability),
+
+            frame: 0,
+            entry_delay: ENTRY_DELAY,
+
+            drop_timer: Some(gravity_value(start_level)),
+            spawn_delay_timer: None,
+            animation_timer: None,
+
+            soft_drop: false,
+            animation_info: CompleteLines::new(),
+
+            left_timer: None,
+            right_timer: None,
+
+            hold_used: false,
+            gameover,
+            finished: false,
+
+            events: vec![Event::Spawn],
+        }
+    }
+
+    pub fn map(&self) -> &Map {
+        &self.map
+    }
+
+    pub fn current(&self) -> &Tetrimino {
+        &self.current
+    }
+
+    pub fn next(&self) -> &VecDeque<Tetrimino> {
+        &self.next
+    }
+
+    pub fn hold(&self) -> Option<&Tetrimino> {
+        self.hold.as_ref()
+    }
+
+    pub fn score(&self) -> usize {
+        self.score
+    }
+
+    pub fn lines(&self) -> usize {
+        self.lines
+    }
+
+    pub fn level(&self) -> usize {
+        self.level
+    }
+
+    pub fn stats(&self) -> &Statistics {
+        &self.stats
+    }
+
+    pub fn pending_garbage(&self) -> usize {
+        self.pending_garbage
+    }
+
+    /// Adds garbage sent by the opponent (cancelled by the next attacks or inserted at the next lock without clears).
+    pub fn receive_garbage(&mut self, rows: usize) {
+        self.pending_garbage += rows;
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.drop_timer.is_some()
+    }
+
+    pub fn is_gameover(&self) -> bool {
+        self.gameover
+    }
+
+    /// Whether the goal of the game mode was reached.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.gameover || self.finished
+    }
+
+    /// Frames simulated so far (60 per second).
+    pub fn frame(&self) -> usize {
+        self.frame
+    }
+
+    pub fn time_millis(&self) -> usize {
+        self.frame * 1000 / 60
+    }
+
+    pub fn next_tile_types(&self) -> Vec<TileType> {
+        self.next.iter().map(|tet| tet.tile_type).collect()
+    }
+
+    /// Events of the last step (performed actions, spawns, finesse faults, attacks), or of the
+    /// construction before the first step. Cleared at the start of every step.
+    pub fn drain_events(&mut self) -> Vec<Event> {
+        self.events.drain(..).collect()
+    }
+
+    fn initial_map(rules: &Rules, seed: [u8; 32]) -> Map {
+        let mut map = Map::new(&rules.board());
+
+        if let GameMode::Dig { rows, hole_change_probability } = rules.mode {
+            let mut gen = GarbageGenerator::new(garbage::seed(seed), map.width(), hole_change_probability);
+            // keep the spawn area free
+            let rows = cmp::min(rows, map.visible_height() - 2);
+            for _ in 0..rows {
+                map.push_garbage(gen.next_hole(), 0);
+            }
+        }
+
+        map
+    }
+
+    fn next_queue(rules: &Rules, gen: &mut dyn RandomGenerator, rotation: &dyn RotationSystem) -> VecDeque<Tetrimino> {
+        let board = rules.board();
+        (0..rules.preview_count())
+            .map(|_| rotation.spawn(gen.next(), &board))
+            .collect()
+    }
+
+    fn pop_next(&mut self) -> Tetrimino {
+        let next = self.rotation.spawn(self.gen.next(), &self.board);
+        self.next.push_back(next);
+
+        let current = self.next.pop_front().unwrap();
+        self.stats.on_spawn(current.tile_type);
+        current
+    }
+
+    fn line_counter(start_level: usize) -> isize {
+        let level = start_level as isize;
+        cmp::min(level * 10 + 10, cmp::max(100, level * 10 - 50)) as isize
+    }
+
+    fn rotate(&mut self, right: bool) {
+        if let Some(tet) = self.rotation.rotate(&self.current, &self.map, right) {
+            self.current = tet;
+        }
+    }
+
+    fn rotate_left(&mut self) {
+        self.events.push(Event::Action(Action::RotateLeft));
+        self.rotate(false);
+    }
+
+    fn rotate_right(&mut self) {
+        self.events.push(Event::Action(Action::RotateRight));
+        self.rotate(true);
+    }
+
+    fn mov(&mut self, x_off: i32, y_off: i32) -> bool {
+        self.current.mov(&self.map, x_off, y_off)
+    }
+
+    fn shift_once(&mut self, action: Action, x_off: i32) -> bool {
+        self.events.push(Event::Action(action));
+        self.mov(x_off, 0)
+    }
+
+    /// Shifts while the key is held (delayed auto shift) and returns the new shift timer.
+    fn shift(&mut self, timer: Option<usize>, action: Action, x_off: i32) -> Option<usize> {
+        match timer {
+            None => {
+                // first shift on key press
+                self.shift_once(action, x_off);
+                return Some(self.handling.das);
+            },
+            // the timer counts the frames without a shift (as on the NES)
+            Some(timer) if timer > 0 => return Some(timer - 1),
+            Some(_) => (),
+        }
+
+        let moved = if self.handling.arr == 0 {
+            let mut moved = false;
+            while self.shift_once(action, x_off) {
+                moved = true;
+            }
+            moved
+        } else {
+            self.shift_once(action, x_off)
+        };
+
+        if moved {
+            Some(self.handling.arr)
+        } else {
+            match self.handling.das_charge {
+                DasCharge::ResetOnWall => Some(self.handling.das),
+                DasCharge::Preserve => Some(0),
+            }
+        }
+    }
+
+    fn drop(&mut self) -> bool {
+        self.events.push(Event::Action(Action::Drop));
+        self.mov(0, 1)
+    }
+
+    fn hard_drop(&mut self, rules: &Rules) {
+        if rules.hard_drop_enabled {
+            while self.drop() {}
+        }
+    }
+
+    fn hold_current(&mut self) {
+        // only one hold per spawn (and only while a tetrimino is active)
+        if self.hold_used || self.drop_timer.is_none() {
+            return;
+        }
+
+        self.events.push(Event::Action(Action::Hold));
+
+        // held tetriminos always return at their spawn position
+        let held = self.rotation.spawn(self.current.piece, &self.board);
+
+        self.current = match self.hold.take() {
+            Some(tet) => tet,
+            None => self.pop_next(),
+        };
+        self.hold = Some(held);
+        self.hold_used = true;
+        self.finesse.reset();
+
+        self.events.push(Event::Spawn);
+
+        // reset drop timer
+        self.drop_timer = Some(gravity_value(self.level));
+
+        // game over
+        if self.map.collision(&self.current) {
+            self.gameover = true;
+        }
+    }
+
+    fn update_score(&mut self, complete_lines: usize) {
+        self.stats.on_clear(complete_lines);
+        self.lines += complete_lines;
+        self.line_counter -= complete_lines as isize;
+
+        if self.line_counter <= 0 {
+            self.level += 1;
+            self.line_counter += 10;
+        }
+
+        let factor = match complete_lines {
+            1 => 40,
+            2 => 100,
+            3 => 300,
+            // larger pieces can clear more lines
+            _ => 1200,
+        };
+        self.score += factor * (self.level + 1);
+    }
+
+    /// Sends garbage for a line clear, which cancels the pending garbage first.
+    fn attack(&mut self, rules: &Rules, complete_lines: usize) {
+        let combo = self.combo.map_or(0, |combo| combo + 1);
+        self.combo = Some(combo);
+
+        let rows = rules.attack.rows(complete_lines, combo);
+        let cancelled = cmp::min(rows, self.pending_garbage);
+        self.pending_garbage -= cancelled;
+
+        if rows > cancelled {
+            self.events.push(Event::Attack(rows - cancelled));
+        }
+    }
+
+    /// Pushes the pending garbage under the stack and returns whether tiles were pushed out of the top.
+    fn insert_garbage(&mut self) -> bool {
+        let mut overflow = false;
+
+        for _ in 0..mem::take(&mut self.pending_garbage) {
+            overflow |= self.map.push_garbage(self.garbage.next_hole(), self.frame);
+        }
+
+        overflow
+    }
+
+    fn check_finesse(&mut self) {
+        if let Some(keys) = finesse::check(self.rotation.as_ref(), &self.board, &self.current, self.finesse.presses()) {
+            self.stats.finesse_faults += 1;
+            self.events.push(Event::FinesseFault(keys));
+        }
+
+        // keys pressed from now on count for the next tetrimino
+        self.finesse.reset();
+    }
+
+    /// Whether the current tetrimino locks entirely within the hidden rows (checked before the lock).
+    fn is_lock_out(&self, rules: &Rules) -> bool {
+        rules.top_out == TopOut::Guideline && self.current.cells().all(|(_, y)| y < self.map.hidden_rows() as i32)
+    }
+
+    /// Whether the next tetrimino overlaps the stack after the lock (classic top out).
+    fn is_top_out_on_lock(&self, rules: &Rules) -> bool {
+        rules.top_out == TopOut::Classic && self.map.collision(&self.next[0])
+    }
+
+    /// Whether the spawned tetrimino ends the game (block out).
+    fn is_top_out_on_spawn(&self, rules: &Rules) -> bool {
+        rules.top_out != TopOut::Classic && self.map.collision(&self.current)
+    }
+
+    fn update_drop(&mut self, rules: &Rules) -> bool {
+        self.check_finesse();
+        let lock_out = self.is_lock_out(rules);
+
+        // tetrimino -> map
+        self.map.apply(&self.current, self.frame);
+        let mut top_out = lock_out || self.is_top_out_on_lock(rules);
+
+        self.entry_delay = if rules.classic_timing {
+            let lowest = self.current.cells().map(|(_, y)| y).max().unwrap_or(0);
+            let lock_height = self.map.height().saturating_sub(lowest as usize + 1);
+            classic_entry_delay(lock_height)
+        } else {
+            ENTRY_DELAY
+        };
+
+        // check for complete lines
+        self.animation_info = self.map.complete_lines();
+        if !self.animation_info.is_empty() {
+            // update score
+            self.update_score(self.animation_info.len());
+            self.attack(rules, self.animation_info.len());
+
+            // trigger animation
+            self.animation_timer = if rules.classic_timing {
+                Some(classic_line_clear_delay(self.frame))
+            } else {
+                Some(LINE_CLEAR_DELAY)
+            };
+        } else {
+            self.combo = None;
+
+            if self.pending_garbage > 0 {
+                // the next tetrimino also has to fit above the raised stack
+                top_out = self.insert_garbage() || top_out || self.is_top_out_on_lock(rules);
+            }
+
+            // trigger spawn delay
+            self.spawn_delay_timer = Some(self.entry_delay);
+        }
+
+        self.drop_timer = None;
+
+        top_out
+    }
+
+    /// Advances the simulation by one frame.
+    pub fn step(&mut self, rules: &Rules, input: Input) {
+        self.events.clear();
+        if self.is_over() {
+            return;
+        }
+
+        self.input(rules, input);
+        if self.gameover {
+            return;
+        }
+
+        self.update(rules, input);
+        self.frame += 1;
+
+        self.finished = !self.gameover && self.goal_reached(rules);
+    }
+
+    fn goal_reached(&self, rules: &Rules) -> bool {
+        match rules.mode {
+            GameMode::Marathon => false,
+            GameMode::Sprint { lines } => self.lines >= lines,
+            GameMode::Ultra { frames } => self.frame >= frames,
+            // after the line clear animation
+            GameMode::Dig { .. } => self.animation_timer.is_none() && self.map.garbage_rows() == 0,
+        }
+    }
+
+    fn update(&mut self, rules: &Rules, input: Input) {
+        // gravity
+        if self.auto_drop {
+            if let Some(timer) = self.drop_timer {
+                if timer == 0 {
+                    if !self.drop() {
+                        self.gameover = self.update_drop(rules);
+                        if self.gameover {
+                            return;
+                        }
+                    } else {
+                        // reset drop timer
+                        self.drop_timer = Some(gravity_value(self.level));
+                    }
+                } else if self.soft_drop {
+                    self.drop_timer = Some(timer.saturating_sub(cmp::max(self.handling.soft_drop_factor, 1)));
+                } else {
+                    self.drop_timer = Some(timer - 1);
+                }
+            }
+        } else if input.contains(Action::Drop) && self.drop_timer.is_some() && !self.drop() {
+            self.gameover = self.update_drop(rules);
+            if self.gameover {
+                return;
+            }
+        }
+
+        // clear line animation
+        if let Some(timer) = self.animation_timer {
+            if timer == 0 {
+                self.map.clear(&self.animation_info);
+
+                // trigger spawn delay
+                self.spawn_delay_timer = Some(self.entry_delay);
+
+                self.animation_timer = None;
+            } else {
+                if timer % 4 == 0 && timer < LINE_CLEAR_DELAY {
+                    // advance animation (from the center outwards, the last step is the clear itself)
+                    let count = self.animation_info.len();
+
+                    let width = self.map.width();
+                    let half = width.div_ceil(2);
+                    let progress = LINE_CLEAR_STEPS + 1 - (timer / 4 + 1);
+                    let reach = (progress * half).div_ceil(LINE_CLEAR_STEPS);
+                    for i in 0..count {
+                        let y = self.animation_info[i];
+                        for x in (half - reach)..half {
+                            self.map.set(x, y, TileType::Empty);
+                            self.map.set(width - 1 - x, y, TileType::Empty);
+                        }
+                    }
+                }
+
+                self.animation_timer = Some(timer - 1);
+            }
+        }
+
+        // ARE (spawn delay)
+        if let Some(timer) = self.spawn_delay_timer {
+            if timer == 0 {
+                // spawn tetrimino
+                self.current = self.pop_next();
+                self.hold_used = false;
+
+                self.events.push(Event::Spawn);
+
+                // reset drop timer
+                self.drop_timer = Some(gravity_value(self.level));
+
+                self.spawn_delay_timer = None;
+
+                // game over
+                self.gameover = self.is_top_out_on_spawn(rules);
+            } else {
+                self.spawn_delay_timer = Some(timer - 1);
+            }
+        }
+    }
+
+    fn input(&mut self, rules: &Rules, input: Input) {
+        self.finesse.input(input);
+        self.soft_drop = input.contains(Action::SoftDrop);
+
+        self.left_timer = if input.contains(Action::MoveLeft) {
+            self.shift(self.left_timer, Action::MoveLeft, -1)
+        } else {
+            None
+        };
+
+        self.right_timer = if input.contains(Action::MoveRight) {
+            self.shift(self.right_timer, Action::MoveRight, 1)
+        } else {
+            None
+        };
+
+        if input.contains(Action::Hold) {
+            self.hold_current();
+        }
+
+        if input.contains(Action::RotateLeft) {
+            self.rotate_left();
+        }
+
+        if input.contains(Action::RotateRight) {
+            self.rotate_right();
+        }
+
+        if input.contains(Action::HardDrop) {
+            self.hard_drop(rules)
+        }
+    }
+
+    pub fn reset(&mut self, rules: &Rules, seed: [u8; 32]) {
+        *self = Simulation::new(rules, &self.handling, seed, self.auto_drop);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEED: [u8; 32] = [0; 32];
+
+    fn rules(top_out: TopOut) -> Rules {
+        let mut rules: Rules = serde_json::from_value(serde_json::json!({
+            "mode": "Marathon",
+            "board": { "width": 10, "visible_height": 20, "hidden_rows": 2 },
+            "random_generator": "RandomBag",
+            "start_level": 8,
+            "rotation_system": "RotationSRS",
+            "hard_drop_enabled": true,
+            "ghost_enabled": true,
+            "stack_visibility": "Visible",
+            "classic_timing": false,
+            "preview_count": 1,
+        })).unwrap();
+        rules.top_out = top_out;
+        rules
+    }
+
+    fn input(actions: &[Action]) -> Input {
+        let mut input = Input::default();
+        for &action in actions {
+            input.set(action);
+        }
+        input
+    }
+
+    /// Steps without input until the next tetrimino spawned (or the game is over).
+    fn step_until_spawn(sim: &mut Simulation, rules: &Rules) {
+        for _ in 0..200 {
+            sim.step(rules, Input::default());
+            if sim.is_over() || sim.drain_events().iter().any(|event| matches!(event, Event::Spawn)) {
+                return;
+            }
+        }
+        panic!("no tetrimino spawned");
+    }
+
+    #[test]
+    fn gravity_moves_down_once_per_interval() {
+        let rules = rules(TopOut::Classic);
+        let mut sim = Simulation::new(&rules, &Handling::default(), SEED, true);
+        let y = sim.current().pos.y;
+
+        for _ in 0..gravity_value(rules.start_level) {
+            sim.step(&rules, Input::default());
+        }
+        assert_eq!(sim.current().pos.y, y);
+
+        sim.step(&rules, Input::default());
+        assert_eq!(sim.current().pos.y, y + 1);
+    }
+
+    #[test]
+    fn hard_drop_locks_at_the_ghost() {
+        let rules = rules(TopOut::Classic);
+        let mut sim = Simulation::new(&rules, &Handling::default(), SEED, true);
+        let ghost = sim.current().ghost(sim.map());
+        let tile_type = ghost.tile_type;
+
+        sim.step(&rules, input(&[Action::HardDrop]));
+        step_until_spawn(&mut sim, &rules);
+
+        assert!(!sim.is_over());
+        for (x, y) in ghost.cells() {
+            assert!(sim.map().get(x as usize, y as usize) == tile_type);
+        }
+    }
+
+    #[test]
+    fn filled_row_is_cleared() {
+        let rules = rules(TopOut::Classic);
+        let mut sim = Simulation::new(&rules, &Handling::default(), SEED, true);
+        let ghost = sim.current().ghost(sim.map());
+
+        // everything but the lowest cells of the tetrimino
+        let bottom = sim.map.height() - 1;
+        for x in 0..sim.map.width() {
+            if !ghost.cells().any(|cell| cell == (x as i32, bottom as i32)) {
+                sim.map.set(x, bottom, TileType::Garbage);
+            }
+        }
+
+        sim.step(&rules, input(&[Action::HardDrop]));
+        step_until_spawn(&mut sim, &rules);
+
+        assert_eq!(sim.lines(), 1);
+        assert!(sim.score() > 0);
+        assert!((0..sim.map().width()).all(|x| sim.map().get(x, bottom) != TileType::Garbage));
+    }
+
+    #[test]
+    fn hard_drops_end_the_game() {
+        let rules = rules(TopOut::Classic);
+        let mut sim = Simulation::new(&rules, &Handling::default(), SEED, true);
+
+        for _ in 0..100 {
+            sim.step(&rules, input(&[Action::HardDrop]));
+            step_until_spawn(&mut sim, &rules);
+            if sim.is_over() {
+                break;
+            }
+        }
+
+        assert!(sim.is_gameover());
+        let frame = sim.frame();
+        sim.step(&rules, input(&[Action::HardDrop]));
+        assert_eq!(sim.frame(), frame);
+    }
+
+    /// Fills the visible rows (except the right column), so the next tetrimino locks within the hidden rows.
+    fn fill_visible_rows(sim: &mut Simulation) {
+        for y in sim.map.hidden_rows()..sim.map.height() {
+            for x in 0..sim.map.width() - 1 {
+                sim.map.set(x, y, TileType::Garbage);
+            }
+        }
+    }
+
+    #[test]
+    fn classic_top_out_checks_the_next_tetrimino_after_the_lock() {
+        let rules = rules(TopOut::Classic);
+        let mut sim = Simulation::new(&rules, &Handling::default(), SEED, true);
+
+        while !sim.is_over() {
+            sim.step(&rules, input(&[Action::HardDrop]));
+            step_until_spawn(&mut sim, &rules);
+            // no tetrimino spawns overlapping the stack
+            assert!(sim.is_over() || !sim.map().collision(sim.current()));
+        }
+
+        // game over at the lock, before the next tetrimino spawned
+        assert!(sim.is_gameover() && !sim.is_active());
+        assert!(sim.map().collision(&sim.next()[0]));
+    }
+
+    #[test]
+    fn block_out_ends_the_game_at_spawn() {
+        let rules = rules(TopOut::BlockOut);
+        let mut sim = Simulation::new(&rules, &Handling::default(), SEED, true);
+        fill_visible_rows(&mut sim);
+
+        sim.step(&rules, input(&[Action::HardDrop]));
+        step_until_spawn(&mut sim, &rules);
+
+        assert!(sim.is_gameover() && sim.is_active());
+        assert!(sim.map().collision(sim.current()));
+    }
+
+    #[test]
+    fn lock_out_ends_the_game_at_the_lock() {
+        let rules = rules(TopOut::Guideline);
+        let mut sim = Simulation::new(&rules, &Handling::default(), SEED, true);
+        fill_visible_rows(&mut sim);
+
+        sim.step(&rules, input(&[Action::HardDrop]));
+        step_until_spawn(&mut sim, &rules);
+
+        assert!(sim.is_gameover() && !sim.is_active());
+        assert!(sim.current().cells().all(|(_, y)| y < sim.map().hidden_rows() as i32));
+    }
+
+    #[test]
+    fn spawn_outside_of_the_board_ends_the_game() {
+        let file = std::fs::File::open(concat!(env!("CARGO_MANIFEST_DIR"), "/resources/pieces/big.json")).unwrap();
+        let mut rules: Rules = serde_json::from_value(serde_json::json!({
+            "mode": "Marathon",
+            "board": { "width": 4, "visible_height": 20, "hidden_rows": 2 },
+            "random_generator": "RandomBag",
+            "start_level": 8,
+            "rotation_system": "RotationSRS",
+            "hard_drop_enabled": true,
+            "ghost_enabled": true,
+            "stack_visibility": "Visible",
+            "classic_timing": false,
+            "preview_count": 1,
+        })).unwrap();
+        rules.piece_set = Some(crate::piece::load(file).unwrap());
+
+        for &top_out in [TopOut::Classic, TopOut::BlockOut, TopOut::Guideline].iter() {
+            rules.top_out = top_out;
+            let mut sim = Simulation::new(&rules, &Handling::default(), SEED, true);
+            assert!(sim.is_gameover());
+
+            sim.step(&rules, input(&[Action::HardDrop]));
+            assert_eq!(sim.frame(), 0);
+        }
+    }
+
+    #[test]
+    fn auto_shift_keeps_the_nes_timing() {
+        let rules = rules(TopOut::Classic);
+        let handling = Handling::default();
+        let mut sim = Simulation::new(&rules, &handling, SEED, true);
+
+        let mut shifts = Vec::new();
+        for frame in 0..30 {
+            let x = sim.current().pos.x;
+            sim.step(&rules, input(&[Action::MoveRight]));
+            if sim.current().pos.x != x {
+                shifts.push(frame);
+            }
+        }
+
+        // a frame without shift for every frame of das and arr
+        assert_eq!(shifts[..3], [0, handling.das + 1, handling.das + handling.arr + 2]);
+        assert_eq!(shifts[1], 17);
+    }
+
+    #[test]
+    fn inserted_garbage_is_locked_in_the_current_frame() {
+        let rules = rules(TopOut::Classic);
+        let mut sim = Simulation::new(&rules, &Handling::default(), SEED, true);
+        sim.receive_garbage(2);
+
+        // locks without clearing lines
+        sim.step(&rules, input(&[Action::HardDrop]));
+        while sim.is_active() {
+            sim.step(&rules, Input::default());
+        }
+
+        let bottom = sim.map().height() - 1;
+        assert_eq!(sim.map().garbage_rows(), 2);
+        assert!((0..sim.map().width()).all(|x| sim.map().lock_frame(x, bottom) == sim.frame() - 1));
+    }
+}
@@ -1,33 +1,39 @@
 use crate::engine::Context;
 
 pub mod player;
+pub mod replay;
+pub mod network;
 
 use crate::settings::Settings;
 use crate::tetrimino::TileType;
 use crate::map::Map;
 
-#[derive(Copy, Clone, PartialEq)]
-pub enum Action {
-    RotateLeft,
-    RotateRight,
-    MoveLeft,
-    MoveRight,
-    SoftDrop,
-    HardDrop,
-    Drop,
-}
+pub use crate::simulation::{Action, Input};
 
 pub trait Actor {
     fn is_auto_drop(&self) -> bool {
         true
     }
 
-    fn on_spawn(&mut self, _settings: &Settings, _map: &Map, _current: TileType, _next: TileType, _score: usize, _lines: usize, _level: usize) {
+    fn on_spawn(&mut self, _settings: &Settings, _map: &Map, _current: TileType, _next: &[TileType], _score: usize, _lines: usize, _level: usize) {
     }
 
     fn check(&mut self, ctx: &mut Context, action: Action) -> bool;
     fn push(&mut self, _action: Action) {
     }
 
+    /// Whether the input of the next frame is known (a remote player's inputs arrive over the network).
+    fn is_ready(&self) -> bool {
+        true
+    }
+
+    /// Garbage rows the player received (at the other end) before the next frame.
+    fn garbage(&self) -> usize {
+        0
+    }
+
+    fn receive(&mut self, _input: Input, _garbage: usize) {
+    }
+
     fn update(&mut self, ctx: &mut Context);
 }
\ No newline at end of file
@@ -0,0 +1,44 @@
+use crate::engine::Context;
+use std::collections::VecDeque;
+
+use super::{Actor, Action, Input};
+
+/// The player at the other end of a versus connection, replaying the inputs it streams.
+#[derive(Default)]
+pub struct NetworkActor {
+    /// Input and received garbage of each frame.
+    frames: VecDeque<(Input, usize)>,
+}
+
+impl NetworkActor {
+    pub fn new() -> NetworkActor {
+        NetworkActor {
+            frames: VecDeque::new(),
+        }
+    }
+}
+
+impl Actor for NetworkActor {
+    fn is_ready(&self) -> bool {
+        !self.frames.is_empty()
+    }
+
+    fn garbage(&self) -> usize {
+        self.frames.front().map_or(0, |&(_, garbage)| garbage)
+    }
+
+    fn receive(&mut self, input: Input, garbage: usize) {
+        self.frames.push_back((input, garbage));
+    }
+
+    fn check(&mut self, _ctx: &mut Context, action: Action) -> bool {
+        match self.frames.front() {
+            Some((input, _)) => input.contains(action),
+            None => false,
+        }
+    }
+
+    fn update(&mut self, _ctx: &mut Context) {
+        self.frames.pop_front();
+    }
+}
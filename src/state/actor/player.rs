@@ -4,73 +4,43 @@ use crate::engine::{
     Context,
 };
 
+use crate::keymap::{Keymap, BINDABLE_ACTIONS};
 use super::{Actor, Action};
 
 pub struct Player {
-    was_rcontrol_pressed: bool,
-    was_y_pressed: bool,
-    was_z_pressed: bool,
-    was_up_pressed: bool,
-    was_x_pressed: bool,
-    was_space_pressed: bool,
+    keymap: Keymap,
+    /// Bound keys which were pressed in the last frame.
+    was_pressed: Vec<KeyCode>,
 }
 
 impl Player {
-    pub fn new() -> Player {
+    pub fn new(keymap: Keymap) -> Player {
         Player {
-            was_rcontrol_pressed: false,
-            was_y_pressed: false,
-            was_z_pressed: false,
-            was_up_pressed: false,
-            was_x_pressed: false,
-            was_space_pressed: false,
+            keymap,
+            was_pressed: Vec::new(),
         }
     }
 
-    fn is_valid(&mut self, keycode: KeyCode) -> bool {
-        match keycode {
-            KeyCode::RControl => !self.was_rcontrol_pressed,
-            KeyCode::Y => !self.was_y_pressed,
-            KeyCode::Z => !self.was_z_pressed,
-            KeyCode::Up => !self.was_up_pressed,
-            KeyCode::X => !self.was_x_pressed,
-            KeyCode::Space => !self.was_space_pressed,
-            _ => true,
-        }
-    }
-
-    fn check_keys(&mut self, ctx: &mut Context, keycodes: &[KeyCode]) -> bool {
-        for keycode in keycodes {
-            if keyboard::is_key_pressed(ctx, *keycode) {
-                if self.is_valid(*keycode) {
-                    return true;
-                }
-            }
-        }
-
-        false
+    /// Shifts and soft drops repeat while the key is held, the other actions need a new key press.
+    fn is_repeating(action: Action) -> bool {
+        matches!(action, Action::MoveLeft | Action::MoveRight | Action::SoftDrop)
     }
 }
 
 impl Actor for Player {
     fn check(&mut self, ctx: &mut Context, action: Action) -> bool {
-        match action {
-            Action::RotateLeft => self.check_keys(ctx, &[KeyCode::RControl, KeyCode::Y, KeyCode::Z]),
-            Action::RotateRight => self.check_keys(ctx, &[KeyCode::Up, KeyCode::X]),
-            Action::MoveLeft => self.check_keys(ctx, &[KeyCode::Left]),
-            Action::MoveRight => self.check_keys(ctx, &[KeyCode::Right]),
-            Action::SoftDrop => self.check_keys(ctx, &[KeyCode::Down]),
-            Action::HardDrop => self.check_keys(ctx, &[KeyCode::Space]),
-            Action::Drop => false,
-        }
+        let repeating = Player::is_repeating(action);
+
+        self.keymap.keys(action).iter()
+            .any(|key| keyboard::is_key_pressed(ctx, *key) && (repeating || !self.was_pressed.contains(key)))
     }
 
     fn update(&mut self, ctx: &mut Context) {
-        self.was_rcontrol_pressed = keyboard::is_key_pressed(ctx, KeyCode::RControl);
-        self.was_y_pressed = keyboard::is_key_pressed(ctx, KeyCode::Y);
-        self.was_z_pressed = keyboard::is_key_pressed(ctx, KeyCode::Z);
-        self.was_up_pressed = keyboard::is_key_pressed(ctx, KeyCode::Up);
-        self.was_x_pressed = keyboard::is_key_pressed(ctx, KeyCode::X);
-        self.was_space_pressed = keyboard::is_key_pressed(ctx, KeyCode::Space);
+        let keymap = &self.keymap;
+
+        self.was_pressed = BINDABLE_ACTIONS.iter()
+            .flat_map(|&action| keymap.keys(action).iter().copied())
+            .filter(|&key| keyboard::is_key_pressed(ctx, key))
+            .collect();
     }
-}
\ No newline at end of file
+}
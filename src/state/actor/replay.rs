@@ -0,0 +1,35 @@
+use crate::engine::Context;
+
+use crate::replay::Replay;
+use super::{Actor, Action};
+
+pub struct ReplayActor {
+    replay: Replay,
+    frame: usize,
+}
+
+impl ReplayActor {
+    pub fn new(replay: Replay) -> ReplayActor {
+        ReplayActor {
+            replay,
+            frame: 0,
+        }
+    }
+}
+
+impl Actor for ReplayActor {
+    fn check(&mut self, _ctx: &mut Context, action: Action) -> bool {
+        match self.replay.input(self.frame) {
+            Some(input) => input.contains(action),
+            None => false,
+        }
+    }
+
+    fn garbage(&self) -> usize {
+        self.replay.garbage(self.frame)
+    }
+
+    fn update(&mut self, _ctx: &mut Context) {
+        self.frame += 1;
+    }
+}
@@ -0,0 +1,150 @@
+use crate::engine::{
+    event::KeyCode,
+    graphics::{Text, Scale},
+    Context, GameResult,
+};
+
+use crate::settings::Settings;
+use crate::keymap::{self, Conflict, BINDABLE_ACTIONS};
+use super::{State, Resources, StateID};
+use super::actor::Action;
+use super::menu::{self, navigate};
+use super::options::OptionsState;
+
+/// The player row, one row per bindable action and the back row.
+const ITEM_COUNT: usize = BINDABLE_ACTIONS.len() + 2;
+
+fn action_name(action: Action) -> &'static str {
+    match action {
+        Action::RotateLeft => "ROTATE LEFT",
+        Action::RotateRight => "ROTATE RIGHT",
+        Action::MoveLeft => "LEFT",
+        Action::MoveRight => "RIGHT",
+        Action::SoftDrop => "SOFT DROP",
+        Action::HardDrop => "HARD DROP",
+        Action::Hold => "HOLD",
+        Action::Drop => "DROP",
+    }
+}
+
+fn key_name(key: KeyCode) -> String {
+    format!("{:?}", key).to_uppercase()
+}
+
+/// Rebinding of the keys of each local player (saved to the settings file when leaving).
+///
+/// Return captures the next key press for the selected action, Backspace removes all of its keys.
+pub struct ControlsState {
+    selected: usize,
+    player: usize,
+    capturing: bool,
+    /// Why the last captured key was not bound.
+    message: Option<String>,
+}
+
+impl ControlsState {
+    pub fn new() -> ControlsState {
+        ControlsState {
+            selected: 0,
+            player: 0,
+            capturing: false,
+            message: None,
+        }
+    }
+
+    fn action(&self) -> Option<Action> {
+        self.selected.checked_sub(1).and_then(|index| BINDABLE_ACTIONS.get(index).copied())
+    }
+
+    fn items(&self, settings: &Settings) -> Vec<String> {
+        let keymap = &settings.keymaps[self.player];
+
+        let mut items = vec![format!("PLAYER {}", self.player + 1)];
+        items.extend(BINDABLE_ACTIONS.iter().map(|&action| {
+            let keys: Vec<String> = keymap.keys(action).iter().map(|&key| key_name(key)).collect();
+            format!("{} {}", action_name(action), keys.join(" "))
+        }));
+        items.push("BACK".to_string());
+        items
+    }
+
+    fn title(&self) -> String {
+        if self.capturing {
+            "PRESS A KEY".to_string()
+        } else {
+            self.message.clone().unwrap_or_else(|| "CONTROLS".to_string())
+        }
+    }
+
+    fn capture(&mut self, settings: &mut Settings, action: Action, keycode: KeyCode) {
+        self.capturing = false;
+
+        self.message = match keymap::conflict(&settings.keymaps, self.player, action, keycode) {
+            None => {
+                settings.keymaps[self.player].bind(action, keycode);
+                None
+            },
+            Some(Conflict::Reserved) => Some(format!("{} IS RESERVED", key_name(keycode))),
+            Some(Conflict::Bound(player, _)) if player != self.player => Some(format!("TAKEN: PLAYER {}", player + 1)),
+            Some(Conflict::Bound(_, bound)) => Some(format!("TAKEN: {}", action_name(bound))),
+        };
+    }
+
+    fn switch_player(&mut self, settings: &Settings) {
+        self.player = (self.player + 1) % settings.keymaps.len();
+    }
+}
+
+impl State for ControlsState {
+    fn update(&mut self, _ctx: &mut Context, _settings: &Settings, _res: &Resources) -> GameResult<StateID> {
+        Ok(StateID::Controls)
+    }
+
+    fn draw(&mut self, ctx: &mut Context, settings: &Settings, res: &Resources) -> GameResult<()> {
+        let mut title_text = Text::new(self.title());
+        title_text.set_font(res.font, Scale::uniform(settings.font.size_popup));
+
+        menu::draw_screen(ctx, settings, res, &title_text, &self.items(settings), self.selected)
+    }
+
+    fn key_down_event(&mut self, _ctx: &mut Context, settings: &mut Settings, keycode: KeyCode, repeat: bool) -> StateID {
+        if self.capturing {
+            match self.action() {
+                _ if repeat => (),
+                // cancel
+                _ if keycode == KeyCode::Escape => self.capturing = false,
+                Some(action) => self.capture(settings, action, keycode),
+                None => self.capturing = false,
+            }
+            return StateID::Controls;
+        }
+
+        self.message = None;
+
+        match keycode {
+            KeyCode::Up | KeyCode::Down => self.selected = navigate(self.selected, ITEM_COUNT, keycode),
+            KeyCode::Left | KeyCode::Right if self.selected == 0 => self.switch_player(settings),
+
+            KeyCode::Back | KeyCode::Delete => if let Some(action) = self.action() {
+                settings.keymaps[self.player].clear(action);
+            },
+
+            KeyCode::Escape => {
+                OptionsState::save(settings);
+                return StateID::Settings;
+            },
+            KeyCode::Return if !repeat => match self.action() {
+                Some(_) => self.capturing = true,
+                None if self.selected == 0 => self.switch_player(settings),
+                None => {
+                    OptionsState::save(settings);
+                    return StateID::Settings;
+                },
+            },
+
+            _ => (),
+        }
+
+        StateID::Controls
+    }
+}
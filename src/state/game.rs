@@ -8,484 +8,952 @@ use crate::engine::{
     },
     Context, GameResult,
 };
-use std::cmp;
+use std::{
+    fs::{self, File},
+    mem,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 use crate::tetrimino::{TileType, Tetrimino};
 use crate::settings::{self, Settings};
-use crate::random::{self, RandomGenerator};
-use crate::map::{Map, CompleteLines};
-use super::{State, Resources, StateID};
+use crate::rules::{Rules, Board, Handling, GameMode, StackVisibility};
+use crate::rotation::{self, RotationSystemType};
+use crate::simulation::{Simulation, Input, Event};
+use crate::replay::Replay;
+use crate::net::{Connection, Message, Profile};
+use crate::records;
+use crate::stats::{Statistics, Report};
+use crate::leaderboard::{Leaderboard, Category, Entry, TABLE_SIZE};
+use super::{State, Resources, StateID, StateHandler};
+use super::leaderboard;
 use super::actor::{
     Action, Actor,
     player::Player,
+    replay::ReplayActor,
+    network::NetworkActor,
 };
+use super::render::{self, draw_text, draw_string, draw_lines};
 
-pub fn gravity_value(level: usize) -> usize {
-    match level {
-        0 => 48,
-        1 => 43,
-        2 => 38,
-        3 => 33,
-        4 => 28,
-        5 => 23,
-        6 => 18,
-        7 => 13,
-        8 => 8,
-        9 => 6,
-        10 => 5,
-        11 => 5,
-        12 => 5,
-        13 => 4,
-        14 => 4,
-        15 => 4,
-        16 => 3,
-        17 => 3,
-        18 => 3,
-        19 => 2,
-        20 => 2,
-        21 => 2,
-        22 => 2,
-        23 => 2,
-        24 => 2,
-        25 => 2,
-        26 => 2,
-        27 => 2,
-        28 => 2,
-        _ => 1,
-    }
-}
+const PREVIEW_SLOT_TILES: f32 = 2.5;
+const PREVIEW_SMALL_SCALE: f32 = 0.5;
 
-struct GameInstance {
-    actor: Box<dyn Actor>,
+const PLAYBACK_SPEED_MAX: usize = 8;
 
-    gen: Box<dyn RandomGenerator>,
+/// Frames locked tiles flash when the stack is not visible.
+const LOCK_FLASH_FRAMES: usize = 8;
 
-    map: Map,
-    current: Tetrimino,
-    next: Tetrimino,
+const STATS_ICON_SCALE: f32 = 0.4;
 
-    score: usize,
-    lines: usize,
-    level: usize,
+/// Frames a finesse fault is shown in place of the player name.
+const FINESSE_HINT_FRAMES: usize = 90;
 
-    line_counter: isize,
+struct GameInstance {
+    actor: Box<dyn Actor>,
+    nickname: String,
+
+    rules: Rules,
+    handling: Handling,
+    sim: Simulation,
+    /// Frames the instance was updated (unlike the frames of the simulation this includes the frame of a top out).
+    frames: usize,
 
     player_text: Text,
     score_text: Text,
+    time_text: Text,
     lines_text: Text,
     level_text: Text,
     next_text: Text,
+    hold_text: Text,
 
-    drop_timer: Option<usize>,
-    spawn_delay_timer: Option<usize>,
-    animation_timer: Option<usize>,
+    /// One tetrimino of each tile type for the stats panel.
+    stats_icons: Vec<Tetrimino>,
 
-    soft_drop: bool,
-    animation_info: CompleteLines,
+    /// Last finesse fault (optimal keys in trainer mode) and the frames it is still shown.
+    finesse_hint: Option<String>,
+    finesse_hint_timer: usize,
 
-    left_timer: Option<usize>,
-    right_timer: Option<usize>,
+    /// Rank of the game in the leaderboard (if it made it into the table).
+    leaderboard_rank: Option<usize>,
 
-    gameover: bool,
+    /// Garbage rows sent since the last update.
+    sent_garbage: usize,
+    /// Garbage rows received since the last update (streamed to the other end with the next input).
+    received_garbage: usize,
 }
 
 impl GameInstance {
-    fn new(settings: &Settings, res: &Resources, seed: [u8; 32], player: String, _left: bool) -> GameInstance {
-        let mut actor = Box::new(Player::new());
-
-        let mut gen = random::create(seed, settings.random_generator);
-        let current = Tetrimino::new(gen.next());
-        let next = Tetrimino::new(gen.next());
-        
-        let start_level = settings.start_level;
-        let line_counter = GameInstance::line_counter(start_level);
+    fn new(settings: &Settings, res: &Resources, rules: Rules, handling: Handling, seed: [u8; 32], actor: Box<dyn Actor>, nickname: String) -> GameInstance {
+        let sim = Simulation::new(&rules, &handling, seed, actor.is_auto_drop());
 
-        let map = Map::new();
-        actor.on_spawn(settings, &map, current.tile_type, next.tile_type, 0, 0, start_level);
- 
-        let mut player_text = Text::new(player);
+        let mut player_text = Text::new(nickname.as_str());
         let mut score_text = Text::new("SCORE");
+        let mut time_text = Text::new("TIME");
         let mut lines_text = Text::new("LINES");
         let mut level_text = Text::new("LEVEL");
         let mut next_text = Text::new("NEXT");
+        let mut hold_text = Text::new("HOLD");
 
         player_text.set_font(res.font, Scale::uniform(settings.font.size_player));
         score_text.set_font(res.font, Scale::uniform(settings.font.size_default));
+        time_text.set_font(res.font, Scale::uniform(settings.font.size_default));
         lines_text.set_font(res.font, Scale::uniform(settings.font.size_default));
         level_text.set_font(res.font, Scale::uniform(settings.font.size_default));
         next_text.set_font(res.font, Scale::uniform(settings.font.size_default));
+        hold_text.set_font(res.font, Scale::uniform(settings.font.size_default));
 
-        GameInstance {
+        let mut instance = GameInstance {
             actor,
-            
-            gen,
-
-            map,
-            current,
-            next,
-
-            score: 0,
-            lines: 0,
-            level: start_level,
+            nickname,
 
-            line_counter,
+            rules,
+            handling,
+            sim,
+            frames: 0,
 
             player_text,
             score_text,
+            time_text,
             lines_text,
             level_text,
             next_text,
+            hold_text,
 
-            drop_timer: Some(gravity_value(start_level)),
-            spawn_delay_timer: None,
-            animation_timer: None,
+            stats_icons: GameInstance::stats_icons(),
 
-            soft_drop: false,
-            animation_info: CompleteLines::new(),
+            finesse_hint: None,
+            finesse_hint_timer: 0,
 
-            left_timer: None,
-            right_timer: None,
+            leaderboard_rank: None,
 
-            gameover: false,
-        }
-    }
+            sent_garbage: 0,
+            received_garbage: 0,
+        };
 
-    fn line_counter(start_level: usize) -> isize {
-        let level = start_level as isize;
-        cmp::min(level * 10 + 10, cmp::max(100, level * 10 - 50)) as isize
+        instance.dispatch_events(settings);
+        instance
     }
 
-    fn rotate(&mut self, settings: &Settings, right: bool) {
-        self.current.rotate(settings.wall_kicks_enabled, &self.map, right);
+    fn is_gameover(&self) -> bool {
+        self.sim.is_gameover()
     }
 
-    fn rotate_left(&mut self, settings: &Settings) {
-        self.actor.push(Action::RotateLeft);
-        self.rotate(settings, false);
+    fn is_finished(&self) -> bool {
+        self.sim.is_finished()
     }
 
-    fn rotate_right(&mut self, settings: &Settings) {
-        self.actor.push(Action::RotateRight);
-        self.rotate(settings, true);
+    fn is_over(&self) -> bool {
+        self.sim.is_over()
     }
 
-    fn mov(&mut self, x_off: f32, y_off: f32) -> bool {
-        self.current.mov(&self.map, x_off, y_off)
+    /// Whether the instance is still updated in a round which ends at `end_frame` (if known yet).
+    fn is_running(&self, end_frame: Option<usize>) -> bool {
+        !self.is_over() && end_frame.is_none_or(|end_frame| self.frames < end_frame)
     }
 
-    fn left(&mut self) {
-        self.actor.push(Action::MoveLeft);
-        self.mov(-1.0, 0.0);
-    }
+    fn input(&mut self, ctx: &mut Context) -> Input {
+        let mut input = Input::default();
 
-    fn right(&mut self) {
-        self.actor.push(Action::MoveRight);
-        self.mov(1.0, 0.0);
-    }
+        for &action in Action::ALL.iter() {
+            if self.actor.check(ctx, action) {
+                input.set(action);
+            }
+        }
 
-    fn drop(&mut self) -> bool {
-        self.actor.push(Action::Drop);
-        self.mov(0.0, 1.0)
+        input
     }
 
-    fn hard_drop(&mut self, settings: &Settings) {
-        if settings.hard_drop_enabled {
-            while self.drop() {}
+    fn dispatch_events(&mut self, settings: &Settings) {
+        for event in self.sim.drain_events() {
+            match event {
+                Event::Action(action) => self.actor.push(action),
+                Event::Spawn => {
+                    let next_types = self.sim.next_tile_types();
+                    self.actor.on_spawn(settings, self.sim.map(), self.sim.current().tile_type, &next_types, self.sim.score(), self.sim.lines(), self.sim.level());
+                },
+                Event::FinesseFault(keys) => {
+                    let hint = if settings.finesse_trainer_enabled {
+                        keys.iter().map(|key| key.name()).collect::<Vec<_>>().join(" ")
+                    } else {
+                        "FINESSE".to_string()
+                    };
+                    self.finesse_hint = Some(hint);
+                    self.finesse_hint_timer = FINESSE_HINT_FRAMES;
+                },
+                Event::Attack(rows) => self.sent_garbage += rows,
+            }
         }
     }
 
-    fn update_score(&mut self, complete_lines: usize) {
-        self.lines += complete_lines;
-        self.line_counter -= complete_lines as isize;
+    fn update(&mut self, ctx: &mut Context, settings: &Settings) -> Input {
+        let input = self.input(ctx);
+        // garbage a remote player received at the other end
+        self.sim.receive_garbage(self.actor.garbage());
+        self.sim.step(&self.rules, input);
+        self.frames += 1;
+        self.finesse_hint_timer = self.finesse_hint_timer.saturating_sub(1);
 
-        if self.line_counter <= 0 {
-            self.level += 1;
-            self.line_counter = 10 + self.line_counter;
-        }
+        self.dispatch_events(settings);
+        self.actor.update(ctx);
 
-        let factor = match complete_lines {
-            1 => 40,
-            2 => 100,
-            3 => 300,
-            4 => 1200,
-            _ => panic!("dead code"),
-        };
-        self.score += factor * (self.level + 1);
+        input
     }
 
-    fn update_drop(&mut self) -> bool {
-        // tetrimino -> map
-        self.map.apply(&self.current);
-
-        // check for complete lines
-        self.animation_info = self.map.complete_lines();
-        if !self.animation_info.is_empty() {
-            // update score
-            self.update_score(self.animation_info.len());
+    /// Draws the instance into its layout slot of the background.
+    fn draw(&self, ctx: &mut Context, settings: &Settings, slot: usize, batch: &mut SpriteBatch, font: Font) -> GameResult<()> {
+        let map_position = &settings.map_positions[slot];
+        let next_bounds = &settings.next_bounds[slot];
+        let hold_bounds = &settings.hold_bounds[slot];
+        let player_bounds = &settings.player_bounds[slot];
+        let score_bounds = &settings.score_bounds[slot];
+        let lines_bounds = &settings.lines_bounds[slot];
+        let level_bounds = &settings.level_bounds[slot];
+
+        let sim = &self.sim;
+        let level = sim.level();
+
+        let layout = render::MapLayout::new(settings, map_position, sim.map());
+        render::draw_map(batch, sim.map(), level, &layout, |x, y, tile_type| self.tile_color(settings, x, y, tile_type));
+        render::draw_outline(batch, level, &layout, &self.flashing_cells(), settings.tile.lock_flash_color);
+        render::draw_garbage_meter(batch, sim.map(), level, &layout, sim.pending_garbage(), settings.tile.garbage_meter_color);
+        
+        if sim.is_active() {
+            if self.rules.ghost_enabled {
+                let ghost = sim.current().ghost(sim.map());
+                render::draw_tetrimino_map(batch, &ghost, level, &layout, settings.tile.ghost_color);
+            }
 
-            // trigger animation
-            self.animation_timer = Some(20);
-        } else {
-            // trigger spawn delay
-            self.spawn_delay_timer = Some(10); // TODO: correct value
+            render::draw_tetrimino_map(batch, sim.current(), level, &layout, graphics::WHITE);
         }
 
-        self.drop_timer = None;
+        match &self.finesse_hint {
+            Some(hint) if self.finesse_hint_timer > 0 => {
+                let mut text = Text::new(hint.as_str());
+                text.set_font(font, Scale::uniform(settings.font.size_player));
+                draw_text(ctx, settings, player_bounds, &text);
+            },
+            _ => draw_text(ctx, settings, player_bounds, &self.player_text),
+        }
+        
+        draw_preview(ctx, settings, batch, level, next_bounds, &self.next_text, &sim.next().iter().collect::<Vec<_>>());
+        draw_preview(ctx, settings, batch, level, hold_bounds, &self.hold_text, &sim.hold().into_iter().collect::<Vec<_>>());
+        
+        match self.rules.mode {
+            GameMode::Sprint { .. } | GameMode::Dig { .. } => draw_text_and_value(ctx, settings, font, score_bounds, &self.time_text, format_time(sim.time_millis())),
+            _ => draw_text_and_value(ctx, settings, font, score_bounds, &self.score_text, sim.score()),
+        }
+        match self.rules.mode {
+            GameMode::Ultra { frames } => {
+                let remaining = frames.saturating_sub(sim.frame()) * 1000 / 60;
+                draw_text_and_value(ctx, settings, font, lines_bounds, &self.time_text, format_time(remaining));
+            },
+            _ => draw_text_and_value(ctx, settings, font, lines_bounds, &self.lines_text, sim.lines()),
+        }
+        draw_text_and_value(ctx, settings, font, level_bounds, &self.level_text, level);
 
-        // game over
-        if self.map.collision(&self.next) {
-            return true;
+        if settings.stats_panel_enabled && !settings.multiplayer_enabled {
+            self.draw_stats_panel(ctx, settings, batch, font);
         }
 
-        false
+        Ok(())
     }
 
-    fn update(&mut self, ctx: &mut Context, settings: &Settings) {
-        // gravity
-        if self.actor.is_auto_drop() {
-            if let Some(timer) = self.drop_timer {
-                if timer == 0 {
-                    if !self.drop() {
-                        self.gameover = self.update_drop();
-                        if self.gameover {
-                            return;
-                        }
-                    } else {
-                        // reset drop timer
-                        self.drop_timer = Some(gravity_value(self.level));
-                    }
-                } else {
-                    if self.soft_drop && timer >= 2 {
-                        self.drop_timer = Some(timer - 2);
-                    }
-                    else {
-                        self.drop_timer = Some(timer - 1);
-                    }
-                }
-            }
-        } else {
-            if self.actor.check(ctx, Action::Drop) {
-                if !self.drop() {
-                    self.gameover = self.update_drop();
-                    if self.gameover {
-                        return;
-                    }
-                }
-            }
-        }
+    fn stats_summary(&self) -> Vec<String> {
+        let stats = self.sim.stats();
+        let names = ["SINGLES", "DOUBLES", "TRIPLES", "TETRISES"];
 
-        // clear line animation
-        if let Some(timer) = self.animation_timer {
-            if timer == 0 {
-                self.map.clear(&self.animation_info);
-
-                // trigger spawn delay
-                self.spawn_delay_timer = Some(10); // TODO: correct value
+        let mut lines: Vec<String> = names.iter()
+            .zip(stats.clears.iter())
+            .map(|(name, count)| format!("{} {}", name, count))
+            .collect();
+        lines.extend(stats_rates(stats, self.sim.frame()));
+        lines
+    }
 
-                self.animation_timer = None;
-            } else {
-                if timer % 4 == 0 {
-                    // advance animation
-                    let count = self.animation_info.len();
-
-                    let step = timer / 4;
-                    let x0 = step - 1;
-                    let x1 = settings::MAP_WIDTH - step;
-                    for i in 0..count {
-                        let y = self.animation_info[i];
-                        self.map.set(x0, y, TileType::Empty);
-                        self.map.set(x1, y, TileType::Empty);
-                    }
-                }
+    fn stats_icons() -> Vec<Tetrimino> {
+        let rotation = rotation::create(RotationSystemType::RotationNES);
+        let board = Board::default();
+        (0..rotation.piece_count())
+            .map(|piece| rotation.spawn(piece, &board))
+            .collect()
+    }
 
-                self.animation_timer = Some(timer - 1);
-            } 
-        }
+    /// Piece counts (like the NES statistics column) followed by the rates.
+    fn draw_stats_panel(&self, ctx: &mut Context, settings: &Settings, batch: &mut SpriteBatch, font: Font) {
+        let bounds = &settings.stats_bounds;
+        let sim = &self.sim;
+        let stats = sim.stats();
 
-        // ARE (spawn delay)
-        if let Some(timer) = self.spawn_delay_timer {
-            if timer == 0 {
-                // spawn tetrimino
-                self.current = self.next.clone();
-                self.next = Tetrimino::new(self.gen.next());
+        let rates = stats_rates(stats, sim.frame());
+        let row_h = bounds.h / (self.stats_icons.len() + rates.len()) as f32;
+        let icon_size = settings.tile.size * STATS_ICON_SCALE;
 
-                self.actor.on_spawn(settings, &self.map, self.current.tile_type, self.next.tile_type, self.score, self.lines, self.level);
-                
-                // reset drop timer
-                self.drop_timer = Some(gravity_value(self.level));
+        for (tet, count) in self.stats_icons.iter().zip(stats.pieces.iter()) {
+            let y = bounds.y + row_h * (tet.piece as f32 + 0.5);
+            render::draw_tetrimino(batch, tet, sim.level(), Vec2f::new(bounds.x + bounds.w / 4.0, y), icon_size);
 
-                self.spawn_delay_timer = None;
-            } else {
-                self.spawn_delay_timer = Some(timer - 1);
-            }
+            let count_bounds = Rect::new(bounds.x + bounds.w / 2.0, y, bounds.w / 2.0, 0.0);
+            draw_string(ctx, settings, font, &count_bounds, count.to_string());
         }
 
-        self.actor.update(ctx);
+        let y = bounds.y + row_h * self.stats_icons.len() as f32;
+        let rates_bounds = Rect::new(bounds.x, y, bounds.w, bounds.y + bounds.h - y);
+        draw_lines(ctx, settings, font, &rates_bounds, rates);
     }
 
-    fn input(&mut self, ctx: &mut Context, settings: &Settings) {
-        self.soft_drop = self.actor.check(ctx, Action::SoftDrop);
-
-        if self.actor.check(ctx, Action::MoveLeft) {
-            if let Some(timer) = self.left_timer {
-                if timer == 0 {
-                    self.left();
-                    self.left_timer = Some(6);
-                } else {
-                    self.left_timer = Some(timer - 1);
-                }
-            } else {
-	            self.left();
-                self.left_timer = Some(16);
-            }
-	    } else {
-	        self.left_timer = None;
-        }
-
-        if self.actor.check(ctx, Action::MoveRight) {
-            if let Some(timer) = self.right_timer {
-                if timer == 0 {
-                    self.right();
-                    self.right_timer = Some(6);
-                } else {
-                    self.right_timer = Some(timer - 1);
-                }
-            } else {
-	            self.right();
-                self.right_timer = Some(16);
-            }
-	    } else {
-	        self.right_timer = None;
-        }
+    /// Whether locked tiles fade out (invisible and fading stacks are revealed when the game is over).
+    fn is_stack_hidden(&self) -> bool {
+        self.rules.stack_visibility != StackVisibility::Visible && !self.sim.is_over()
+    }
 
-        //KeyCode::Shift => self.instance.hold(),
-        //KeyCode::C => self.instance.hold(),
+    /// Frames since the tile was locked.
+    fn tile_age(&self, x: usize, y: usize) -> usize {
+        self.sim.frame().saturating_sub(self.sim.map().lock_frame(x, y))
+    }
 
-        if self.actor.check(ctx, Action::RotateLeft) {
-            self.rotate_left(settings);
-        }
+    /// Tint of a locked tile.
+    fn tile_color(&self, settings: &Settings, x: usize, y: usize, tile_type: TileType) -> Color {
+        let color = match tile_type {
+            TileType::Garbage => settings.tile.garbage_color,
+            _ => graphics::WHITE,
+        };
 
-        if self.actor.check(ctx, Action::RotateRight) {
-            self.rotate_right(settings);
+        if !self.is_stack_hidden() {
+            return color;
         }
 
-        if self.actor.check(ctx, Action::HardDrop) {
-            self.hard_drop(settings)
-        }
+        // fades out after the flash
+        let age = self.tile_age(x, y).saturating_sub(LOCK_FLASH_FRAMES);
+        color.fade(self.rules.stack_visibility.alpha(age))
     }
 
-    fn draw(&self, ctx: &mut Context, settings: &Settings, batch: &mut SpriteBatch, font: Font) -> GameResult<()> {
-        let map_position = &settings.map_positions[0];
-        let next_bounds = &settings.next_bounds[0];
-        let player_bounds = &settings.player_bounds[0];
-        let score_bounds = &settings.score_bounds[0];
-        let lines_bounds = &settings.lines_bounds[0];
-        let level_bounds = &settings.level_bounds[0];
-
-        self.map.draw(settings, batch, self.level, map_position);
-        
-        if self.drop_timer != None {
-            self.current.draw_map(settings, batch, self.level, map_position);
+    /// Freshly locked tiles, which are outlined while the stack is hidden.
+    fn flashing_cells(&self) -> Vec<(i32, i32)> {
+        if !self.is_stack_hidden() {
+            return Vec::new();
         }
 
-        draw_text(ctx, settings, player_bounds, &self.player_text);
-        
-        let h = 2.0 * settings.font.next_text_y_offset + self.next_text.height(ctx) as f32;
-        let bounds = Rect::new(next_bounds.x, next_bounds.y, next_bounds.w, h);
-        draw_text(ctx, settings, &bounds, &self.next_text);
-        let x = next_bounds.x + next_bounds.w / 2.0;
-        let y = next_bounds.y + bounds.h + (next_bounds.h - bounds.h) / 2.0;
-        self.next.draw(settings, batch, self.level, Vec2f { x, y });
-        
-        draw_text_and_value(ctx, settings, font, score_bounds, &self.score_text, self.score);
-        draw_text_and_value(ctx, settings, font, lines_bounds, &self.lines_text, self.lines);
-        draw_text_and_value(ctx, settings, font, level_bounds, &self.level_text, self.level);
+        let map = self.sim.map();
+        (0..map.height())
+            .flat_map(|y| (0..map.width()).map(move |x| (x, y)))
+            .filter(|&(x, y)| map.get(x, y) != TileType::Empty && self.tile_age(x, y) < LOCK_FLASH_FRAMES)
+            .map(|(x, y)| (x as i32, y as i32))
+            .collect()
+    }
 
-        Ok(())
+    fn reset(&mut self, settings: &Settings, seed: [u8; 32]) {
+        self.sim.reset(&self.rules, seed);
+        self.frames = 0;
+        self.finesse_hint = None;
+        self.finesse_hint_timer = 0;
+        self.leaderboard_rank = None;
+        self.sent_garbage = 0;
+        self.received_garbage = 0;
+        self.dispatch_events(settings);
     }
+}
 
-    fn reset(&mut self, settings: &Settings) {
-        self.map.reset();
-        self.current = Tetrimino::new(self.gen.next());
-        self.next = Tetrimino::new(self.gen.next());
+struct Playback {
+    replay: Replay,
+    frame: usize,
+    speed: usize,
+    step: bool,
+}
 
-        self.score = 0;
-        self.lines = 0;
-        self.level = settings.start_level;
+impl Playback {
+    fn new(replay: Replay) -> Playback {
+        Playback {
+            replay,
+            frame: 0,
+            speed: 1,
+            step: false,
+        }
+    }
 
-        self.line_counter = GameInstance::line_counter(settings.start_level);
+    fn steps(&mut self, running: bool) -> usize {
+        if running {
+            self.speed
+        } else if mem::replace(&mut self.step, false) {
+            1
+        } else {
+            0
+        }
+    }
 
-        self.drop_timer = Some(gravity_value(settings.start_level));
-        self.spawn_delay_timer = None;
-        self.animation_timer = None;
+    fn is_finished(&self) -> bool {
+        self.frame >= self.replay.len()
+    }
+}
 
-        self.soft_drop = false;
-        self.animation_info = CompleteLines::new();
+/// Connection of a network game (the local player is the first instance, the remote one the second).
+struct Session {
+    connection: Connection,
+    host: bool,
+    /// The other end is ready for the current round.
+    remote_ready: bool,
+    disconnected: bool,
+}
 
-        self.left_timer = None;
-        self.right_timer = None;
+impl Session {
+    fn send(&mut self, message: &Message) {
+        if self.connection.send(message).is_err() {
+            self.disconnected = true;
+        }
+    }
 
-        self.gameover = false;
+    fn receive(&mut self) -> Option<Message> {
+        match self.connection.receive() {
+            Ok(message) => message,
+            Err(_) => {
+                self.disconnected = true;
+                None
+            },
+        }
     }
 }
 
 pub struct GameState {
     pause_text: Text,
     gameover_text: Text,
+    results_text: Text,
+    new_best_text: Text,
 
     batch: SpriteBatch,
 
-    instance: GameInstance,
+    /// One instance per player (two in split screen, each drawn in its layout slot).
+    instances: Vec<GameInstance>,
+    /// Shared by all instances, so they get the same piece sequence.
+    seed: [u8; 32],
+
+    /// One replay per instance (they share the seed), none during playback.
+    recordings: Vec<Replay>,
+    /// Where the recordings are saved (also when the state is dropped with the window closed).
+    replay_directory: PathBuf,
+    playback: Option<Playback>,
+    session: Option<Session>,
 
     running: bool,
+
+    results_submitted: bool,
+    new_best: bool,
+    /// Loaded when a live game is over.
+    leaderboard: Leaderboard,
+    show_stats: bool,
+    show_leaderboard: bool,
 }
 
 impl GameState {
     pub fn new(settings: &Settings, res: &Resources, seed: [u8; 32]) -> GameResult<GameState> {
+        let nicknames = [&settings.nickname, &settings.second_nickname];
+        let player_count = if settings.multiplayer_enabled { 2 } else { 1 };
+
+        let instances: Vec<GameInstance> = (0..player_count)
+            .map(|player| {
+                let actor = Box::new(Player::new(settings.keymaps[player].clone()));
+                GameInstance::new(settings, res, settings.rules.clone(), settings.handling.clone(), seed, actor, nicknames[player].clone())
+            })
+            .collect();
+
+        GameState::with_instances(settings, res, instances, seed, None)
+    }
+
+    pub fn new_playback(settings: &Settings, res: &Resources, replay: Replay) -> GameResult<GameState> {
+        let actor = Box::new(ReplayActor::new(replay.clone()));
+        let instance = GameInstance::new(settings, res, replay.rules.clone(), replay.handling.clone(), replay.seed, actor, "REPLAY".to_string());
+        let seed = replay.seed;
+
+        GameState::with_instances(settings, res, vec![instance], seed, Some(Playback::new(replay)))
+    }
+
+    /// Versus game against the other end of the connection, both ends start with the seed.
+    pub fn new_network(settings: &Settings, res: &Resources, connection: Connection, host: bool, rules: Rules, remote: Profile, seed: [u8; 32]) -> GameResult<GameState> {
+        let actor = Box::new(Player::new(settings.keymaps[0].clone()));
+        let local = GameInstance::new(settings, res, rules.clone(), settings.handling.clone(), seed, actor, settings.nickname.clone());
+        let remote = GameInstance::new(settings, res, rules, remote.handling, seed, Box::new(NetworkActor::new()), remote.nickname);
+
+        let mut state = GameState::with_instances(settings, res, vec![local, remote], seed, None)?;
+        state.session = Some(Session {
+            connection,
+            host,
+            remote_ready: false,
+            disconnected: false,
+        });
+        state.send(&Message::Ready);
+
+        Ok(state)
+    }
+
+    fn with_instances(settings: &Settings, res: &Resources, instances: Vec<GameInstance>, seed: [u8; 32], playback: Option<Playback>) -> GameResult<GameState> {
         let mut pause_text = Text::new("PAUSE");
         let mut gameover_text = Text::new("GAME OVER");
+        let mut results_text = Text::new("FINISHED");
+        let mut new_best_text = Text::new("NEW BEST");
 
         pause_text.set_font(res.font, Scale::uniform(settings.font.size_popup));
         gameover_text.set_font(res.font, Scale::uniform(settings.font.size_popup));
+        results_text.set_font(res.font, Scale::uniform(settings.font.size_popup));
+        new_best_text.set_font(res.font, Scale::uniform(settings.font.size_popup));
 
-        let batch = SpriteBatch::new(res.tileset.clone());
-
-        let instance = GameInstance::new(settings, res, seed, settings.nickname.clone(), true);
+        let batch = SpriteBatch::new(res.tileset);
+        let recordings = if playback.is_none() {
+            GameState::new_recordings(&instances, seed)
+        } else {
+            Vec::new()
+        };
 
         let state = GameState {
             pause_text,
             gameover_text,
+            results_text,
+            new_best_text,
 
             batch,
 
-            instance,
+            instances,
+            seed,
+
+            recordings,
+            replay_directory: settings::user_data_path(&settings.replay_directory),
+            playback,
+            session: None,
 
             running: true,
+
+            results_submitted: false,
+            new_best: false,
+            leaderboard: Leaderboard::default(),
+            show_stats: false,
+            show_leaderboard: false,
         };
 
         Ok(state)
     }
 
+    fn new_recordings(instances: &[GameInstance], seed: [u8; 32]) -> Vec<Replay> {
+        instances.iter()
+            .map(|instance| Replay::new(&instance.rules, &instance.handling, seed))
+            .collect()
+    }
+
+    /// Starts a new round (the playback from the beginning).
     fn reset(&mut self, settings: &Settings) {
-        self.instance.reset(settings);
+        if let Some(playback) = &mut self.playback {
+            self.instances[0].actor = Box::new(ReplayActor::new(playback.replay.clone()));
+            playback.frame = 0;
+
+            let seed = playback.replay.seed;
+            self.restart(settings, seed);
+        } else {
+            let seed = StateHandler::generate_seed();
+            // the other end plays the same round
+            self.send(&Message::Start { seed });
+            self.restart(settings, seed);
+        }
+    }
+
+    fn restart(&mut self, settings: &Settings, seed: [u8; 32]) {
+        self.save_recordings();
+
+        self.seed = seed;
+        for instance in &mut self.instances {
+            instance.reset(settings, seed);
+        }
+
+        if !self.is_playback() {
+            self.recordings = GameState::new_recordings(&self.instances, seed);
+        }
+
+        if let Some(session) = &mut self.session {
+            // inputs of the last round are dropped
+            self.instances[1].actor = Box::new(NetworkActor::new());
+            session.remote_ready = false;
+        }
+        self.send(&Message::Ready);
+
         self.running = true;
+
+        self.results_submitted = false;
+        self.new_best = false;
+        self.show_stats = false;
+        self.show_leaderboard = false;
+    }
+
+    fn send(&mut self, message: &Message) {
+        if let Some(session) = &mut self.session {
+            session.send(message);
+        }
+    }
+
+    /// Handles the messages of the other end (the inputs of the remote player are passed to its actor).
+    fn receive(&mut self, settings: &Settings) {
+        let mut start = None;
+
+        if let Some(session) = &mut self.session {
+            while let Some(message) = session.receive() {
+                match message {
+                    Message::Ready => session.remote_ready = true,
+                    // inputs of the last round still arrive until the other end is ready again
+                    Message::Frame { input, garbage } if session.remote_ready => self.instances[1].actor.receive(input, garbage),
+                    Message::Start { seed } if !session.host => {
+                        // the following messages belong to the new round
+                        start = Some(seed);
+                        break;
+                    },
+                    _ => (),
+                }
+            }
+        }
+
+        if let Some(seed) = start {
+            self.restart(settings, seed);
+        }
+    }
+
+    /// Sends the garbage of a line clear to the opponent (in versus games).
+    ///
+    /// Each end of a network game only handles the attacks on its own player, the ones of the local
+    /// player are sent by its copy at the other end.
+    fn attack(&mut self, player: usize, rows: usize) {
+        let count = self.instances.len();
+        if count < 2 || (self.session.is_some() && player == 0) {
+            return;
+        }
+
+        let opponent = &mut self.instances[(player + 1) % count];
+        opponent.sim.receive_garbage(rows);
+        opponent.received_garbage += rows;
+    }
+
+    /// Updates every player by a frame (a remote player whose inputs arrived late catches up).
+    fn step(&mut self, ctx: &mut Context, settings: &Settings) {
+        let lead_frame = self.instances.iter().map(|instance| instance.frames).max().unwrap_or(0);
+
+        for player in 0..self.instances.len() {
+            let mut stepped = false;
+
+            loop {
+                let end_frame = self.end_frame();
+                let instance = &mut self.instances[player];
+                if !instance.is_running(end_frame) || !instance.actor.is_ready() || (stepped && instance.frames >= lead_frame) {
+                    break;
+                }
+
+                let garbage = mem::take(&mut instance.received_garbage);
+                // a remote player received its garbage at the other end
+                let remote_garbage = instance.actor.garbage();
+                let input = instance.update(ctx, settings);
+                let attack = mem::take(&mut instance.sent_garbage);
+                stepped = true;
+
+                if let Some(recording) = self.recordings.get_mut(player) {
+                    recording.record(input, garbage + remote_garbage);
+                }
+                // the local player is streamed to the other end
+                if let (0, Some(session)) = (player, &mut self.session) {
+                    session.send(&Message::Frame { input, garbage });
+                }
+
+                if attack > 0 {
+                    self.attack(player, attack);
+                }
+            }
+        }
+    }
+
+    /// Updates the personal bests and writes the statistics of a (live) game which is over.
+    fn submit_results(&mut self, settings: &Settings) {
+        if self.results_submitted || self.is_playback() {
+            return;
+        }
+        self.results_submitted = true;
+
+        if !self.is_versus() && self.instances[0].is_finished() {
+            self.new_best = self.save_personal_best(settings);
+        }
+        self.save_leaderboard(settings);
+        self.save_statistics(settings);
+    }
+
+    /// Returns whether the result is a new personal best (single player only).
+    fn save_personal_best(&self, settings: &Settings) -> bool {
+        let path = settings::user_data_path(&settings.personal_best_file);
+        let mut records = File::open(&path)
+            .ok()
+            .and_then(|file| records::load(file).ok())
+            .unwrap_or_default();
+
+        let instance = &self.instances[0];
+        let rules = &instance.rules;
+        let new_best = match rules.mode {
+            GameMode::Sprint { lines } => records.submit_sprint(rules.random_generator, lines, instance.sim.frame()),
+            _ => false,
+        };
+
+        if !new_best {
+            return false;
+        }
+
+        let result = File::create(&path)
+            .and_then(|file| records.save(file).map_err(|err| err.into()));
+
+        if let Err(err) = result {
+            println!("Could not save personal bests {}: {}", path.display(), err);
+        }
+
+        true
+    }
+
+    /// Adds the game of every player to the leaderboard (if it makes it into the table).
+    fn save_leaderboard(&mut self, settings: &Settings) {
+        self.leaderboard = leaderboard::load(settings);
+        let date = (timestamp_millis() / 1000) as u64;
+        let mut changed = false;
+
+        for player in 0..self.instances.len() {
+            let instance = &mut self.instances[player];
+            instance.leaderboard_rank = None;
+
+            let category = Category::new(&instance.rules);
+            if !category.is_eligible(instance.is_finished()) {
+                continue;
+            }
+
+            let sim = &instance.sim;
+            let entry = Entry {
+                nickname: instance.nickname.clone(),
+                score: sim.score(),
+                lines: sim.lines(),
+                level: sim.level(),
+                frames: sim.frame(),
+                date,
+                seed: self.seed,
+            };
+
+            let rank = match self.leaderboard.submit(category, entry) {
+                Some(rank) => rank,
+                None => continue,
+            };
+            instance.leaderboard_rank = Some(rank);
+            changed = true;
+
+            // entries of the previous players (same category) at or below the rank move down
+            for other in &mut self.instances[..player] {
+                other.leaderboard_rank = other.leaderboard_rank
+                    .map(|other_rank| if other_rank >= rank { other_rank + 1 } else { other_rank })
+                    .filter(|&other_rank| other_rank < TABLE_SIZE);
+            }
+        }
+
+        if !changed {
+            return;
+        }
+
+        let path = settings::user_data_path(&settings.leaderboard_file);
+        let leaderboard = &self.leaderboard;
+        let result = settings::write_atomic(&path, |file| leaderboard.save(file).map_err(|err| err.into()));
+
+        if let Err(err) = result {
+            println!("Could not save leaderboard {}: {}", path.display(), err);
+        }
+    }
+
+    /// Writes one report per player.
+    fn save_statistics(&self, settings: &Settings) {
+        let directory = settings::user_data_path(&settings.stats_directory);
+        let timestamp = timestamp_millis();
+
+        for (player, instance) in self.instances.iter().enumerate() {
+            let sim = &instance.sim;
+            let stats = sim.stats();
+            let report = Report {
+                nickname: &instance.nickname,
+                seed: self.seed,
+                mode: instance.rules.mode,
+                score: sim.score(),
+                lines: sim.lines(),
+                level: sim.level(),
+                frames: sim.frame(),
+                tetris_rate: stats.tetris_rate(),
+                burn: stats.burn(),
+                pps: stats.pps(sim.frame()),
+                statistics: stats,
+            };
+
+            let path = if self.is_versus() {
+                directory.join(format!("stats_{}_{}.json", timestamp, player + 1))
+            } else {
+                directory.join(format!("stats_{}.json", timestamp))
+            };
+
+            let result = fs::create_dir_all(&directory)
+                .and_then(|_| File::create(&path))
+                .and_then(|file| report.save(file).map_err(|err| err.into()));
+
+            if let Err(err) = result {
+                println!("Could not save statistics {}: {}", path.display(), err);
+            }
+        }
+    }
+
+    /// Writes the replays of the players (one per player, so each can be played back on its own).
+    fn save_recordings(&mut self) {
+        let directory = &self.replay_directory;
+        let timestamp = timestamp_millis();
+        let versus = self.is_versus();
+
+        for (player, replay) in mem::take(&mut self.recordings).into_iter().enumerate() {
+            if replay.is_empty() {
+                continue;
+            }
+
+            let path = if versus {
+                directory.join(format!("replay_{}_{}.json", timestamp, player + 1))
+            } else {
+                directory.join(format!("replay_{}.json", timestamp))
+            };
+
+            let result = fs::create_dir_all(directory)
+                .and_then(|_| File::create(&path))
+                .and_then(|file| replay.save(file).map_err(|err| err.into()));
+
+            if let Err(err) = result {
+                println!("Could not save replay {}: {}", path.display(), err);
+            }
+        }
+    }
+
+    fn is_playback(&self) -> bool {
+        self.playback.is_some()
+    }
+
+    fn is_versus(&self) -> bool {
+        self.instances.len() > 1
+    }
+
+    /// The frame the first player was over in (the round ends with it).
+    fn end_frame(&self) -> Option<usize> {
+        self.instances.iter()
+            .filter(|instance| instance.is_over())
+            .map(|instance| instance.frames)
+            .min()
+    }
+
+    /// A round is over once every player is over or reached the frame of the first one who is.
+    ///
+    /// All players are updated in the same frames locally, but a remote player may lag behind.
+    fn is_over(&self) -> bool {
+        match self.end_frame() {
+            Some(end_frame) => self.instances.iter().all(|instance| instance.is_over() || instance.frames >= end_frame),
+            None => false,
+        }
+    }
+
+    /// Waiting for the other end of a network game (or disconnected).
+    fn is_waiting(&self) -> bool {
+        match &self.session {
+            Some(session) => session.disconnected || !session.remote_ready,
+            None => false,
+        }
+    }
+
+    fn is_disconnected(&self) -> bool {
+        self.session.as_ref().is_some_and(|session| session.disconnected)
+    }
+
+    /// The player who won the versus round (`None` for a draw).
+    ///
+    /// Players who finished in the last frame of the round win, otherwise the ones who did not top out.
+    /// Several winners are ranked by score.
+    fn winner(&self) -> Option<usize> {
+        let end_frame = self.end_frame()?;
+        let players = 0..self.instances.len();
+        let over_at_end = |player: usize| {
+            let instance = &self.instances[player];
+            instance.is_over() && instance.frames == end_frame
+        };
+
+        let finished: Vec<usize> = players.clone()
+            .filter(|&player| over_at_end(player) && self.instances[player].is_finished())
+            .collect();
+        let candidates = if finished.is_empty() {
+            players.filter(|&player| !over_at_end(player)).collect()
+        } else {
+            finished
+        };
+
+        let best_score = candidates.iter()
+            .map(|&player| self.instances[player].sim.score())
+            .max()?;
+
+        let mut best = candidates.into_iter()
+            .filter(|&player| self.instances[player].sim.score() == best_score);
+
+        match (best.next(), best.next()) {
+            (Some(winner), None) => Some(winner),
+            _ => None,
+        }
+    }
+
+    /// Popup of a single player game which is over.
+    fn draw_results(&self, ctx: &mut Context, settings: &Settings, font: Font, popup_bounds: &Rect) {
+        let instance = &self.instances[0];
+
+        if instance.is_finished() {
+            let text = if self.new_best { &self.new_best_text } else { &self.results_text };
+            let sim = &instance.sim;
+            match instance.rules.mode {
+                GameMode::Ultra { .. } => draw_text_and_value(ctx, settings, font, popup_bounds, text, sim.score()),
+                _ => draw_text_and_value(ctx, settings, font, popup_bounds, text, format_time(sim.time_millis())),
+            }
+        } else if instance.is_gameover() {
+            match instance.leaderboard_rank {
+                Some(rank) => draw_text_and_value(ctx, settings, font, popup_bounds, &self.gameover_text, format!("RANK {}", rank + 1)),
+                None => draw_text(ctx, settings, popup_bounds, &self.gameover_text),
+            }
+        }
     }
 }
 
 impl State for GameState {
-    fn update(&mut self, ctx: &mut Context, settings: &Settings) -> GameResult<StateID> {
+    fn update(&mut self, ctx: &mut Context, settings: &Settings, _res: &Resources) -> GameResult<StateID> {
+        self.receive(settings);
+
         while timer::check_update_time(ctx, 60) {
-            if self.running && !self.instance.gameover {
-                self.instance.input(ctx, settings);
-                self.instance.update(ctx, settings);
+            let steps = match &mut self.playback {
+                Some(playback) => playback.steps(self.running),
+                None => if self.running { 1 } else { 0 },
+            };
+
+            for _ in 0..steps {
+                if self.is_over() || self.is_waiting() {
+                    break;
+                }
+
+                if let Some(playback) = &mut self.playback {
+                    if playback.is_finished() {
+                        break;
+                    }
+                    playback.frame += 1;
+                }
+
+                self.step(ctx, settings);
             }
         }
+
+        if self.is_over() {
+            self.submit_results(settings);
+            self.save_recordings();
+        }
         
         Ok(StateID::Game)
     }
 
     fn draw(&mut self, ctx: &mut Context, settings: &Settings, res: &Resources) -> GameResult<()> {
-        let color = if !self.running || self.instance.gameover {
+        let paused = !self.running && !self.is_playback();
+        let over = self.is_over();
+        let waiting = self.is_waiting();
+
+        let color = if paused || over || waiting {
             Color::from(settings.background().gray_color)
         } else {
             graphics::WHITE
@@ -494,7 +962,9 @@ impl State for GameState {
         let draw_param = DrawParam::default()
             .color(color);
 
-        self.instance.draw(ctx, settings, &mut self.batch, res.font)?;
+        for (slot, instance) in self.instances.iter().enumerate() {
+            instance.draw(ctx, settings, slot, &mut self.batch, res.font)?;
+        }
 
         // actual draw calls
         graphics::draw(ctx, &res.background, draw_param)?;
@@ -504,16 +974,46 @@ impl State for GameState {
 
         graphics::draw_queued_text(ctx, draw_param, None, FilterMode::Linear)?;
 
-        if !self.running || self.instance.gameover {
+        if self.show_stats && over {
+            // statistics over the maps (instead of the popup)
+            for (slot, instance) in self.instances.iter().enumerate() {
+                let position = &settings.map_positions[slot];
+                let bounds = Rect::new(position.x, position.y, settings.map_size.x, settings.map_size.y);
+                draw_lines(ctx, settings, res.font, &bounds, instance.stats_summary());
+            }
+            graphics::draw_queued_text(ctx, DrawParam::default(), None, FilterMode::Linear)?;
+        } else if self.show_leaderboard && over {
+            // table of the played category over the whole background (the new entries are highlighted)
+            let position = &settings.map_positions[0];
+            let bounds = Rect::new(0.0, position.y, settings.background().w, settings.map_size.y);
+            let table = self.leaderboard.table(&Category::new(&self.instances[0].rules));
+            let ranks: Vec<usize> = self.instances.iter().filter_map(|instance| instance.leaderboard_rank).collect();
+            leaderboard::draw_table(ctx, settings, res, &bounds, table, &ranks);
+            graphics::draw_queued_text(ctx, DrawParam::default(), None, FilterMode::Linear)?;
+        } else if paused || over || waiting {
             let popup_bounds = &settings.background().popup.bounds;
             let draw_param = DrawParam::default()
                 .dest(Vec2f::new(popup_bounds.x, popup_bounds.y));
             graphics::draw(ctx, &res.popup, draw_param)?;
 
-            if !self.running {
+            if paused {
                 draw_text(ctx, settings, popup_bounds, &self.pause_text);
-            } else if self.instance.gameover {
-                draw_text(ctx, settings, popup_bounds, &self.gameover_text);
+            } else if self.is_versus() {
+                let message = if self.is_disconnected() {
+                    "DISCONNECTED".to_string()
+                } else if !over {
+                    "WAITING".to_string()
+                } else {
+                    match self.winner() {
+                        Some(winner) => format!("{} WINS", self.instances[winner].nickname),
+                        None => "DRAW".to_string(),
+                    }
+                };
+                let mut message_text = Text::new(message);
+                message_text.set_font(res.font, Scale::uniform(settings.font.size_popup));
+                draw_text(ctx, settings, popup_bounds, &message_text);
+            } else {
+                self.draw_results(ctx, settings, res.font, popup_bounds);
             }
             graphics::draw_queued_text(ctx, DrawParam::default(), None, FilterMode::Linear)?;
         }
@@ -521,31 +1021,67 @@ impl State for GameState {
         Ok(())
     }
 
-    fn key_down_event(&mut self, _ctx: &mut Context, settings: &Settings, keycode: KeyCode, repeat: bool) -> StateID {
-        if self.instance.gameover {
+    fn key_down_event(&mut self, _ctx: &mut Context, settings: &mut Settings, keycode: KeyCode, repeat: bool) -> StateID {
+        if self.is_over() {
+            // the host starts the rounds of a network game
+            let can_reset = self.session.as_ref().is_none_or(|session| session.host && !session.disconnected);
+
             match keycode {
                 KeyCode::Return =>  {
-                    self.reset(settings);
+                    if self.session.is_none() {
+                        self.reset(settings);
+                    }
                     return StateID::Menu;
                 },
 
-                KeyCode::R => self.reset(settings),
+                KeyCode::R if can_reset => self.reset(settings),
+                KeyCode::S if !repeat => {
+                    self.show_stats = !self.show_stats;
+                    self.show_leaderboard = false;
+                },
+                KeyCode::L if !repeat => {
+                    self.show_leaderboard = !self.show_leaderboard;
+                    self.show_stats = false;
+                },
 
                 _ => (),
             }
-        } else {
-            if !repeat {
-                if !settings.multiplayer_enabled {
-                    match keycode {
-                        KeyCode::Escape => self.running = !self.running,
-                        KeyCode::F1 => self.running = !self.running,
-                        KeyCode::P => self.running = !self.running,
-    
-                        KeyCode::R => self.reset(settings),
-    
-                        _ => (),
-                    }
-                }
+        } else if let Some(playback) = &mut self.playback {
+            match keycode {
+                KeyCode::Escape => self.running = !self.running,
+                KeyCode::F1 => self.running = !self.running,
+                KeyCode::P => self.running = !self.running,
+
+                // fast-forward (1x, 2x, 4x, 8x)
+                KeyCode::F if !repeat => playback.speed = if playback.speed >= PLAYBACK_SPEED_MAX { 1 } else { playback.speed * 2 },
+                // frame stepping (while paused)
+                KeyCode::N => playback.step = true,
+
+                KeyCode::R if !repeat => self.reset(settings),
+                KeyCode::Return => return StateID::Menu,
+
+                _ => (),
+            }
+        } else if self.session.is_some() {
+            // no pause in network games (leaving closes the connection)
+            if keycode == KeyCode::Escape && !repeat {
+                return StateID::Menu;
+            }
+        } else if !repeat {
+            match keycode {
+                KeyCode::Escape => self.running = !self.running,
+                KeyCode::F1 => self.running = !self.running,
+                KeyCode::P => self.running = !self.running,
+
+                KeyCode::R => self.reset(settings),
+
+                // quit to the menu while paused
+                KeyCode::Return if !self.running => {
+                    self.save_recordings();
+                    return StateID::Menu;
+                },
+
+                _ => (),
             }
         }
 
@@ -553,23 +1089,36 @@ impl State for GameState {
     }
 }
 
-fn text_center_position(ctx: &mut Context, bounds: &Rect, text: &Text) -> Vec2f {
-    let x = bounds.x + (bounds.w - text.width(ctx) as f32) / 2.0;
-    let y = bounds.y + (bounds.h - text.height(ctx) as f32) / 2.0;
-
-    Vec2f {
-        x,
-        y,
+impl Drop for GameState {
+    fn drop(&mut self) {
+        // the window was closed or the state left without saving
+        self.save_recordings();
     }
 }
 
-fn draw_text(ctx: &mut Context, settings: &Settings, bounds: &Rect, text: &Text) {
-    let pos = text_center_position(ctx, bounds, text);
-          
-    graphics::queue_text(ctx, &text, pos, Some(settings.font.color));
+fn timestamp_millis() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis())
+        .unwrap_or(0)
+}
+
+/// Formats a duration as `mm:ss.mmm`.
+pub fn format_time(millis: usize) -> String {
+    format!("{:02}:{:02}.{:03}", millis / 60_000, millis / 1000 % 60, millis % 1000)
+}
+
+fn stats_rates(stats: &Statistics, frames: usize) -> Vec<String> {
+    vec![
+        format!("TRT {:.0}%", stats.tetris_rate() * 100.0),
+        format!("BURN {}", stats.burn()),
+        format!("DRT {}", stats.longest_drought),
+        format!("PPS {:.2}", stats.pps(frames)),
+        format!("FIN {}", stats.finesse_faults),
+    ]
 }
 
-fn draw_text_and_value(ctx: &mut Context, settings: &Settings, font: Font, bounds: &Rect, text: &Text, val: usize) {
+fn draw_text_and_value(ctx: &mut Context, settings: &Settings, font: Font, bounds: &Rect, text: &Text, val: impl ToString) {
     let y = bounds.y + bounds.h / 3.0;
     let new_bounds = Rect::new(bounds.x, y, bounds.w, 0.0);
     draw_text(ctx, settings, &new_bounds, text);
@@ -580,3 +1129,41 @@ fn draw_text_and_value(ctx: &mut Context, settings: &Settings, font: Font, bound
     let new_bounds = Rect::new(bounds.x, y, bounds.w, 0.0);
     draw_text(ctx, settings, &new_bounds, &text);
 }
+
+fn draw_preview(ctx: &mut Context, settings: &Settings, batch: &mut SpriteBatch, level: usize, bounds: &Rect, text: &Text, tets: &[&Tetrimino]) {
+    let h = 2.0 * settings.font.next_text_y_offset + text.height(ctx) as f32;
+    let text_bounds = Rect::new(bounds.x, bounds.y, bounds.w, h);
+    draw_text(ctx, settings, &text_bounds, text);
+
+    if tets.is_empty() {
+        return;
+    }
+
+    let x = bounds.x + bounds.w / 2.0;
+    let h = bounds.h - text_bounds.h;
+
+    if tets.len() == 1 {
+        let y = bounds.y + text_bounds.h + h / 2.0;
+        render::draw_tetrimino(batch, tets[0], level, Vec2f { x, y }, settings.tile.size);
+        return;
+    }
+
+    // first slot in full size, remaining slots share the rest of the bounds
+    let slot_h = PREVIEW_SLOT_TILES * settings.tile.size;
+    let small_slot_h = (h - slot_h).max(0.0) / (tets.len() - 1) as f32;
+    let small_size = settings.tile.size * (small_slot_h / slot_h).min(PREVIEW_SMALL_SCALE);
+
+    let mut y = bounds.y + text_bounds.h + slot_h / 2.0;
+    render::draw_tetrimino(batch, tets[0], level, Vec2f { x, y }, settings.tile.size);
+
+    // bounds too short for more than the first slot
+    if small_slot_h <= 0.0 {
+        return;
+    }
+
+    y += (slot_h + small_slot_h) / 2.0;
+    for tet in tets.iter().skip(1) {
+        render::draw_tetrimino(batch, tet, level, Vec2f { x, y }, small_size);
+        y += small_slot_h;
+    }
+}
\ No newline at end of file
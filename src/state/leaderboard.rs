@@ -0,0 +1,165 @@
+use crate::engine::{
+    event::KeyCode,
+    graphics::{
+        self, DrawParam, Text, Scale, FilterMode, Rect,
+    },
+    Context, GameResult,
+};
+use std::fs::{self, File};
+
+use crate::settings::{self, Settings};
+use crate::leaderboard::{self, Leaderboard, Category, Table, Entry};
+use super::{State, Resources, StateID};
+use super::menu::{mode_name, generator_name, rotation_name, top_out_name};
+use super::game::format_time;
+use super::render::{draw_text, draw_list};
+
+/// Share of the background height above the table (title and category).
+const HEADER_HEIGHT: f32 = 0.25;
+
+pub fn load(settings: &Settings) -> Leaderboard {
+    let path = settings::user_data_path(&settings.leaderboard_file);
+    let file = match File::open(&path) {
+        Ok(file) => file,
+        // no results yet
+        Err(_) => return Leaderboard::default(),
+    };
+
+    match leaderboard::load(file) {
+        Ok(leaderboard) => leaderboard,
+        Err(err) => {
+            // keep the corrupt file, the next result would replace it
+            let backup = path.with_extension("bak");
+            println!("Could not load leaderboard {}: {}", path.display(), err);
+            match fs::rename(&path, &backup) {
+                Ok(()) => println!("Moved to {}", backup.display()),
+                Err(err) => println!("Could not move it to {}: {}", backup.display(), err),
+            }
+
+            Leaderboard::default()
+        },
+    }
+}
+
+pub fn category_name(category: &Category) -> String {
+    let hard_drop = if category.hard_drop_enabled { " HD" } else { "" };
+    let timing = if category.classic_timing { " NES TIMING" } else { "" };
+    let board = category.board;
+    let mut name = format!("{} {}/{}{} L{} {}X{} {}{}",
+        mode_name(&category.mode), generator_name(category.random_generator), rotation_name(category.rotation_system), hard_drop,
+        category.start_level, board.width, board.visible_height, top_out_name(category.top_out), timing);
+
+    if let Some(piece_set) = &category.piece_set {
+        name += &format!(" {}", piece_set.to_uppercase());
+    }
+
+    name
+}
+
+fn row(category: &Category, rank: usize, entry: &Entry) -> String {
+    let result = if category.is_timed() {
+        format_time(entry.frames * 1000 / 60)
+    } else {
+        entry.score.to_string()
+    };
+
+    format!("{:>2} {:<8.8} {:>9} {}", rank + 1, entry.nickname, result, leaderboard::format_date(entry.date))
+}
+
+pub fn rows(table: &Table) -> Vec<String> {
+    table.entries.iter()
+        .enumerate()
+        .map(|(rank, entry)| row(&table.category, rank, entry))
+        .collect()
+}
+
+/// Draws the table (or a placeholder if there are no entries) into the bounds.
+pub fn draw_table(ctx: &mut Context, settings: &Settings, res: &Resources, bounds: &Rect, table: Option<&Table>, highlighted: &[usize]) {
+    let mut items = table.map(rows).unwrap_or_default();
+    if items.is_empty() {
+        items.push("NO ENTRIES".to_string());
+    }
+
+    // same height for every row, whatever the number of entries
+    let row_h = bounds.h / leaderboard::TABLE_SIZE as f32;
+    let bounds = Rect::new(bounds.x, bounds.y, bounds.w, row_h * items.len() as f32);
+    draw_list(ctx, settings, res.font, settings.font.size_stats, &bounds, &items, highlighted);
+}
+
+/// High score tables, one category at a time (starting with the one of the current rules).
+pub struct LeaderboardState {
+    title_text: Text,
+    leaderboard: Leaderboard,
+    selected: usize,
+}
+
+impl LeaderboardState {
+    pub fn new(settings: &Settings, res: &Resources) -> LeaderboardState {
+        let mut title_text = Text::new("LEADERBOARD");
+        title_text.set_font(res.font, Scale::uniform(settings.font.size_popup));
+
+        let leaderboard = load(settings);
+        let category = Category::new(&settings.rules);
+        let selected = leaderboard.tables().iter()
+            .position(|table| table.category == category)
+            .unwrap_or(0);
+
+        LeaderboardState {
+            title_text,
+            leaderboard,
+            selected,
+        }
+    }
+
+    /// Index of the newest entry (the last game played in the category).
+    fn newest(table: &Table) -> Option<usize> {
+        table.entries.iter()
+            .enumerate()
+            .max_by_key(|(_, entry)| entry.date)
+            .map(|(rank, _)| rank)
+    }
+}
+
+impl State for LeaderboardState {
+    fn update(&mut self, _ctx: &mut Context, _settings: &Settings, _res: &Resources) -> GameResult<StateID> {
+        Ok(StateID::Leaderboard)
+    }
+
+    fn draw(&mut self, ctx: &mut Context, settings: &Settings, res: &Resources) -> GameResult<()> {
+        let background = settings.background();
+        let draw_param = DrawParam::default()
+            .color(background.gray_color);
+        graphics::draw(ctx, &res.background, draw_param)?;
+
+        let header_h = background.h * HEADER_HEIGHT;
+        draw_text(ctx, settings, &Rect::new(0.0, 0.0, background.w, header_h / 2.0), &self.title_text);
+
+        let table = self.leaderboard.tables().get(self.selected);
+        if let Some(table) = table {
+            let mut category_text = Text::new(format!("< {} >", category_name(&table.category)));
+            category_text.set_font(res.font, Scale::uniform(settings.font.size_stats));
+            draw_text(ctx, settings, &Rect::new(0.0, header_h / 2.0, background.w, header_h / 2.0), &category_text);
+        }
+
+        let bounds = Rect::new(0.0, header_h, background.w, background.h - header_h);
+        let newest = table.and_then(LeaderboardState::newest);
+        draw_table(ctx, settings, res, &bounds, table, newest.as_slice());
+
+        graphics::draw_queued_text(ctx, DrawParam::default(), None, FilterMode::Linear)
+    }
+
+    fn key_down_event(&mut self, _ctx: &mut Context, _settings: &mut Settings, keycode: KeyCode, _repeat: bool) -> StateID {
+        let count = self.leaderboard.tables().len();
+
+        match keycode {
+            KeyCode::Left if count > 0 => self.selected = (self.selected + count - 1) % count,
+            KeyCode::Right if count > 0 => self.selected = (self.selected + 1) % count,
+
+            KeyCode::Escape | KeyCode::Return => return StateID::Menu,
+
+            _ => (),
+        }
+
+        StateID::Leaderboard
+    }
+}
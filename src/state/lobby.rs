@@ -0,0 +1,175 @@
+use crate::engine::{
+    event::KeyCode,
+    graphics::{Text, Scale},
+    Context, GameResult,
+};
+use std::{
+    io, mem,
+    sync::mpsc::{Receiver, TryRecvError},
+};
+
+use crate::settings::Settings;
+use crate::net::{self, Host, Connection, Message, Profile, PROTOCOL_VERSION};
+use super::{State, Resources, StateID, StateHandler};
+use super::game::GameState;
+use super::menu;
+
+enum Phase {
+    Hosting(Host),
+    Joining(Receiver<io::Result<Connection>>),
+    /// Connected, waiting for the handshake (and the seed of the host).
+    Handshake {
+        connection: Connection,
+        remote: Option<Box<Profile>>,
+    },
+    Playing(Box<GameState>),
+    Failed(String),
+}
+
+/// Hosts or joins a network game at the address of the settings, then runs the game.
+pub struct LobbyState {
+    /// `StateID::Host` or `StateID::Join`.
+    id: StateID,
+    phase: Phase,
+}
+
+impl LobbyState {
+    pub fn new(settings: &Settings, id: StateID) -> LobbyState {
+        let phase = if id == StateID::Host {
+            match Host::bind(&settings.connection) {
+                Ok(host) => Phase::Hosting(host),
+                Err(err) => LobbyState::failed(err),
+            }
+        } else {
+            Phase::Joining(net::join(&settings.connection))
+        };
+
+        LobbyState {
+            id,
+            phase,
+        }
+    }
+
+    fn is_host(&self) -> bool {
+        self.id == StateID::Host
+    }
+
+    fn failed(err: io::Error) -> Phase {
+        Phase::Failed(err.to_string().to_uppercase())
+    }
+
+    /// Starts the handshake by sending the own profile.
+    fn connected(settings: &Settings, mut connection: Connection) -> Phase {
+        let profile = Profile {
+            nickname: settings.nickname.clone(),
+            rules: settings.rules.clone(),
+            handling: settings.handling.clone(),
+        };
+
+        match connection.send(&Message::Hello { version: PROTOCOL_VERSION, profile: Box::new(profile) }) {
+            Ok(()) => Phase::Handshake {
+                connection,
+                remote: None,
+            },
+            Err(err) => LobbyState::failed(err),
+        }
+    }
+
+    fn advance(phase: Phase, host: bool, settings: &Settings, res: &Resources) -> GameResult<Phase> {
+        let phase = match phase {
+            Phase::Hosting(listener) => match listener.accept() {
+                Ok(Some(connection)) => LobbyState::connected(settings, connection),
+                Ok(None) => Phase::Hosting(listener),
+                Err(err) => LobbyState::failed(err),
+            },
+            Phase::Joining(joining) => match joining.try_recv() {
+                Ok(Ok(connection)) => LobbyState::connected(settings, connection),
+                Ok(Err(err)) => LobbyState::failed(err),
+                Err(TryRecvError::Empty) => Phase::Joining(joining),
+                Err(TryRecvError::Disconnected) => Phase::Failed("COULD NOT CONNECT".to_string()),
+            },
+            Phase::Handshake { mut connection, remote } => match (connection.receive(), remote) {
+                (Ok(Some(Message::Hello { version, .. })), _) if version != PROTOCOL_VERSION => Phase::Failed("OTHER VERSION".to_string()),
+                (Ok(Some(Message::Hello { profile, .. })), _) if host => {
+                    // the host decides the seed once it knows the other end
+                    let seed = StateHandler::generate_seed();
+                    match connection.send(&Message::Start { seed }) {
+                        Ok(()) => Phase::Playing(Box::new(GameState::new_network(settings, res, connection, host, settings.rules.clone(), *profile, seed)?)),
+                        Err(err) => LobbyState::failed(err),
+                    }
+                },
+                (Ok(Some(Message::Hello { profile, .. })), None) => Phase::Handshake {
+                    connection,
+                    remote: Some(profile),
+                },
+                // the rules of the host are played
+                (Ok(Some(Message::Start { seed })), Some(remote)) if !host => {
+                    let rules = remote.rules.clone();
+                    Phase::Playing(Box::new(GameState::new_network(settings, res, connection, host, rules, *remote, seed)?))
+                },
+                (Ok(_), remote) => Phase::Handshake {
+                    connection,
+                    remote,
+                },
+                (Err(_), _) => Phase::Failed("DISCONNECTED".to_string()),
+            },
+            phase => phase,
+        };
+
+        Ok(phase)
+    }
+
+    fn title(&self) -> String {
+        match &self.phase {
+            Phase::Hosting(_) => "WAITING FOR PLAYER".to_string(),
+            Phase::Joining(_) => "CONNECTING".to_string(),
+            Phase::Handshake { .. } => "CONNECTED".to_string(),
+            Phase::Playing(_) => String::new(),
+            Phase::Failed(message) => message.clone(),
+        }
+    }
+}
+
+impl State for LobbyState {
+    fn update(&mut self, ctx: &mut Context, settings: &Settings, res: &Resources) -> GameResult<StateID> {
+        if let Phase::Playing(game) = &mut self.phase {
+            return match game.update(ctx, settings, res)? {
+                StateID::Game => Ok(self.id),
+                id => Ok(id),
+            };
+        }
+
+        let phase = mem::replace(&mut self.phase, Phase::Failed(String::new()));
+        self.phase = LobbyState::advance(phase, self.is_host(), settings, res)?;
+
+        Ok(self.id)
+    }
+
+    fn draw(&mut self, ctx: &mut Context, settings: &Settings, res: &Resources) -> GameResult<()> {
+        if let Phase::Playing(game) = &mut self.phase {
+            return game.draw(ctx, settings, res);
+        }
+
+        let mut title_text = Text::new(self.title());
+        title_text.set_font(res.font, Scale::uniform(settings.font.size_popup));
+
+        let action = if self.is_host() { "HOST" } else { "JOIN" };
+        let items = [format!("{} {}", action, settings.connection), "BACK".to_string()];
+        menu::draw_screen(ctx, settings, res, &title_text, &items, 1)
+    }
+
+    fn key_down_event(&mut self, ctx: &mut Context, settings: &mut Settings, keycode: KeyCode, repeat: bool) -> StateID {
+        if let Phase::Playing(game) = &mut self.phase {
+            return match game.key_down_event(ctx, settings, keycode, repeat) {
+                StateID::Game => self.id,
+                id => id,
+            };
+        }
+
+        match keycode {
+            // cancel (closes the connection)
+            KeyCode::Escape | KeyCode::Return => StateID::Menu,
+            _ => self.id,
+        }
+    }
+}
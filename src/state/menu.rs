@@ -1,37 +1,216 @@
-//use crate::engine::{
-//    timer,
-//    vec::Vec2f,
-//    event::{KeyCode, KeyMods},
-//    graphics::{
-//        self, DrawParam, Text, Font, Scale, FilterMode,
-//        spritebatch::SpriteBatch,
-//    },
-//    Context, GameResult,
-//};
-//use std::cmp;
-//
-//use crate::tetrimino::{TileType, Tetrimino};
-//use crate::settings::{self, Settings, Bounds};
-//use crate::random::{self, RandomGenerator};
-//use super::{State, Resources, StateID};
-//use super::actor::{
-//    Action, Actor,
-//    player::Player,
-//};
-//
-//pub struct Menu {
-//}
-//
-//impl State for Menu {
-//    fn update(&mut self, ctx: &mut Context, settings: &Settings) -> GameResult<StateID> {
-//        Ok(State::Menu)
-//    }
-//
-//    fn draw(&mut self, ctx: &mut Context, settings: &Settings, res: &Resources) -> GameResult<()> {
-//        Ok(())
-//    }
-//
-//    fn key_down_event(&mut self, _ctx: &mut Context, settings: &Settings, keycode: KeyCode, _keymods: KeyMods, repeat: bool) -> StateID {
-//        State::Menu
-//    }
-//}
\ No newline at end of file
+use crate::engine::{
+    event::KeyCode,
+    graphics::{
+        self, DrawParam, Text, Scale, FilterMode, Rect,
+    },
+    Context, GameResult,
+};
+
+use crate::settings::Settings;
+use crate::rules::{GameMode, TopOut, START_LEVEL_MAX};
+use crate::random::RandomGeneratorType;
+use crate::rotation::RotationSystemType;
+use super::{State, Resources, StateID};
+use super::render::{draw_text, draw_menu};
+
+/// Modes selectable in the title menu.
+const MODES: [GameMode; 4] = [
+    GameMode::Marathon,
+    GameMode::Sprint { lines: 40 },
+    GameMode::Ultra { frames: 3 * 60 * 60 },
+    GameMode::Dig { rows: 10, hole_change_probability: 0.3 },
+];
+
+pub const GENERATORS: [RandomGeneratorType; 2] = [
+    RandomGeneratorType::RandomNES,
+    RandomGeneratorType::RandomBag,
+];
+
+/// Share of the background height above the menu items (title).
+const TITLE_HEIGHT: f32 = 0.25;
+
+#[derive(Copy, Clone, PartialEq)]
+enum MenuItem {
+    Start,
+    Mode,
+    Level,
+    Generator,
+    Settings,
+    Leaderboard,
+    Host,
+    Join,
+}
+
+const ITEMS: [MenuItem; 8] = [
+    MenuItem::Start,
+    MenuItem::Host,
+    MenuItem::Join,
+    MenuItem::Mode,
+    MenuItem::Level,
+    MenuItem::Generator,
+    MenuItem::Settings,
+    MenuItem::Leaderboard,
+];
+
+/// Network games need the multiplayer layout.
+fn menu_items(settings: &Settings) -> Vec<MenuItem> {
+    ITEMS.iter()
+        .copied()
+        .filter(|item| settings.multiplayer_enabled || !matches!(item, MenuItem::Host | MenuItem::Join))
+        .collect()
+}
+
+pub fn mode_name(mode: &GameMode) -> String {
+    match *mode {
+        GameMode::Marathon => "MARATHON".to_string(),
+        GameMode::Sprint { lines } => format!("SPRINT {}", lines),
+        GameMode::Ultra { frames } => format!("ULTRA {}:{:02}", frames / 3600, frames / 60 % 60),
+        GameMode::Dig { rows, .. } => format!("DIG {}", rows),
+    }
+}
+
+pub fn generator_name(random_generator: RandomGeneratorType) -> &'static str {
+    match random_generator {
+        RandomGeneratorType::RandomNES => "NES",
+        RandomGeneratorType::RandomBag => "BAG",
+    }
+}
+
+pub fn rotation_name(rotation_system: RotationSystemType) -> &'static str {
+    match rotation_system {
+        RotationSystemType::RotationNES => "NES",
+        RotationSystemType::RotationSRS => "SRS",
+        RotationSystemType::RotationARS => "ARS",
+    }
+}
+
+pub fn top_out_name(top_out: TopOut) -> &'static str {
+    match top_out {
+        TopOut::Classic => "CLASSIC",
+        TopOut::BlockOut => "BLOCK OUT",
+        TopOut::Guideline => "GUIDELINE",
+    }
+}
+
+/// Moves the selection of a menu with `count` items (wraps around).
+pub fn navigate(selected: usize, count: usize, keycode: KeyCode) -> usize {
+    match keycode {
+        KeyCode::Up => (selected + count - 1) % count,
+        KeyCode::Down => (selected + 1) % count,
+        _ => selected,
+    }
+}
+
+/// Steps through the values of a list (wraps around, unknown values start at the first one).
+pub fn cycle<T: Copy + PartialEq>(values: &[T], current: T, forward: bool) -> T {
+    let count = values.len();
+    let index = match values.iter().position(|&value| value == current) {
+        Some(index) if forward => (index + 1) % count,
+        Some(index) => (index + count - 1) % count,
+        None => 0,
+    };
+    values[index]
+}
+
+/// Next (or previous) start level (wraps around).
+pub fn step_level(level: usize, forward: bool) -> usize {
+    if forward {
+        (level + 1) % (START_LEVEL_MAX + 1)
+    } else {
+        (level + START_LEVEL_MAX) % (START_LEVEL_MAX + 1)
+    }
+}
+
+/// Draws the background (grayed) with a title above the menu items.
+pub fn draw_screen(ctx: &mut Context, settings: &Settings, res: &Resources, title: &Text, items: &[String], selected: usize) -> GameResult<()> {
+    let background = settings.background();
+    let draw_param = DrawParam::default()
+        .color(background.gray_color);
+    graphics::draw(ctx, &res.background, draw_param)?;
+
+    let title_h = background.h * TITLE_HEIGHT;
+    draw_text(ctx, settings, &Rect::new(0.0, 0.0, background.w, title_h), title);
+
+    let bounds = Rect::new(0.0, title_h, background.w, background.h - title_h);
+    draw_menu(ctx, settings, res.font, &bounds, items, selected);
+
+    graphics::draw_queued_text(ctx, DrawParam::default(), None, FilterMode::Linear)
+}
+
+/// Title menu: game setup and the other screens.
+pub struct MenuState {
+    title_text: Text,
+    selected: usize,
+}
+
+impl MenuState {
+    pub fn new(settings: &Settings, res: &Resources) -> MenuState {
+        let mut title_text = Text::new("TETRIS");
+        title_text.set_font(res.font, Scale::uniform(settings.font.size_popup));
+
+        MenuState {
+            title_text,
+            selected: 0,
+        }
+    }
+
+    fn items(&self, settings: &Settings) -> Vec<String> {
+        let rules = &settings.rules;
+
+        menu_items(settings).iter()
+            .map(|item| match item {
+                MenuItem::Start => "START".to_string(),
+                MenuItem::Host => "HOST".to_string(),
+                MenuItem::Join => "JOIN".to_string(),
+                MenuItem::Mode => mode_name(&rules.mode),
+                MenuItem::Level => format!("LEVEL {}", rules.start_level),
+                MenuItem::Generator => format!("RANDOM {}", generator_name(rules.random_generator)),
+                MenuItem::Settings => "SETTINGS".to_string(),
+                MenuItem::Leaderboard => "LEADERBOARD".to_string(),
+            })
+            .collect()
+    }
+
+    fn change(&self, settings: &mut Settings, forward: bool) {
+        let item = menu_items(settings)[self.selected];
+        let rules = &mut settings.rules;
+
+        match item {
+            MenuItem::Mode => rules.mode = cycle(&MODES, rules.mode, forward),
+            MenuItem::Level => rules.start_level = step_level(rules.start_level, forward),
+            MenuItem::Generator => rules.random_generator = cycle(&GENERATORS, rules.random_generator, forward),
+            _ => (),
+        }
+    }
+}
+
+impl State for MenuState {
+    fn update(&mut self, _ctx: &mut Context, _settings: &Settings, _res: &Resources) -> GameResult<StateID> {
+        Ok(StateID::Menu)
+    }
+
+    fn draw(&mut self, ctx: &mut Context, settings: &Settings, res: &Resources) -> GameResult<()> {
+        draw_screen(ctx, settings, res, &self.title_text, &self.items(settings), self.selected)
+    }
+
+    fn key_down_event(&mut self, _ctx: &mut Context, settings: &mut Settings, keycode: KeyCode, _repeat: bool) -> StateID {
+        match keycode {
+            KeyCode::Up | KeyCode::Down => self.selected = navigate(self.selected, menu_items(settings).len(), keycode),
+            KeyCode::Left => self.change(settings, false),
+            KeyCode::Right => self.change(settings, true),
+
+            KeyCode::Return => match menu_items(settings)[self.selected] {
+                MenuItem::Start => return StateID::Game,
+                MenuItem::Host => return StateID::Host,
+                MenuItem::Join => return StateID::Join,
+                MenuItem::Settings => return StateID::Settings,
+                MenuItem::Leaderboard => return StateID::Leaderboard,
+                _ => self.change(settings, true),
+            },
+
+            _ => (),
+        }
+
+        StateID::Menu
+    }
+}
@@ -14,25 +14,44 @@ use std::{
 
 pub mod game;
 mod menu;
+mod options;
+mod controls;
+mod leaderboard;
+mod lobby;
 pub mod actor;
+mod render;
 
 use crate::settings::Settings;
+use crate::replay::Replay;
 use game::GameState;
+use menu::MenuState;
+use options::OptionsState;
+use controls::ControlsState;
+use leaderboard::LeaderboardState;
+use lobby::LobbyState;
 
+#[derive(Copy, Clone, PartialEq)]
 pub enum StateID {
     Game,
     Menu,
+    Settings,
+    Controls,
+    Leaderboard,
+    /// Network game (waits for the other player first).
+    Host,
+    Join,
 }
 
+/// A screen. The returned `StateID` selects the screen shown next (its own one to stay).
 pub trait State {
-    fn update(&mut self, ctx: &mut Context, settings: &Settings) -> GameResult<StateID>;
+    fn update(&mut self, ctx: &mut Context, settings: &Settings, res: &Resources) -> GameResult<StateID>;
     fn draw(&mut self, ctx: &mut Context, settings: &Settings, res: &Resources) -> GameResult<()>;
 
-    fn key_down_event(&mut self, _ctx: &mut Context, settings: &Settings, keycode: KeyCode, repeat: bool) -> StateID;
+    /// Menus change the settings (rules of the next game).
+    fn key_down_event(&mut self, _ctx: &mut Context, settings: &mut Settings, keycode: KeyCode, repeat: bool) -> StateID;
 }
 
 pub struct Resources {
-    #[allow(dead_code)]
     sound: Source,
     pub tileset: Image,
     pub background: Image,
@@ -72,25 +91,69 @@ pub struct StateHandler {
     settings: Settings,
     res: Resources,
 
-    state: GameState,
+    /// The title menu at the bottom, the active state on top.
+    states: Vec<(StateID, Box<dyn State>)>,
+    /// Transition requested by a key event (applied in the next update).
+    next_state: Option<StateID>,
 }
 
 impl StateHandler {
-    pub fn new(ctx: &mut Context, settings: Settings) -> GameResult<StateHandler> {
+    pub fn new(ctx: &mut Context, settings: Settings, replay: Option<Replay>) -> GameResult<StateHandler> {
         let res =  Resources::new(ctx, &settings)?;
-        let seed = StateHandler::generate_seed();
-        let state = GameState::new(&settings, &res, seed)?;
+
+        let mut states: Vec<(StateID, Box<dyn State>)> = vec![(StateID::Menu, Box::new(MenuState::new(&settings, &res)))];
+        if let Some(replay) = replay {
+            states.push((StateID::Game, Box::new(GameState::new_playback(&settings, &res, replay)?)));
+        }
 
         let handler = StateHandler {
             settings,
             res,
 
-            state,
+            states,
+            next_state: None,
         };
 
         Ok(handler)
     }
 
+    fn state_id(&self) -> StateID {
+        self.states.last().unwrap().0
+    }
+
+    fn create_state(&self, id: StateID) -> GameResult<Box<dyn State>> {
+        let settings = &self.settings;
+        let res = &self.res;
+
+        let state: Box<dyn State> = match id {
+            StateID::Game => Box::new(GameState::new(settings, res, StateHandler::generate_seed())?),
+            StateID::Menu => Box::new(MenuState::new(settings, res)),
+            StateID::Settings => Box::new(OptionsState::new(settings, res)),
+            StateID::Controls => Box::new(ControlsState::new()),
+            StateID::Leaderboard => Box::new(LeaderboardState::new(settings, res)),
+            StateID::Host | StateID::Join => Box::new(LobbyState::new(settings, id)),
+        };
+
+        Ok(state)
+    }
+
+    /// Returns to the state if it is on the stack, pushes a new one otherwise.
+    fn switch(&mut self, id: StateID) -> GameResult<()> {
+        if id == self.state_id() {
+            return Ok(());
+        }
+
+        match self.states.iter().position(|(state_id, _)| *state_id == id) {
+            Some(index) => self.states.truncate(index + 1),
+            None => {
+                let state = self.create_state(id)?;
+                self.states.push((id, state));
+            },
+        }
+
+        Ok(())
+    }
+
     fn generate_seed() -> [u8; 32] {
         let mut seed: [u8; 32] = [0; 32];
 
@@ -112,7 +175,14 @@ impl EventHandler for StateHandler {
     fn update(&mut self, ctx: &mut Context) -> GameResult<()> {
         //println!("FPS: {} ({} frames) - UPS: {} ({} ticks)", timer::fps(ctx), timer::frames(ctx), timer::ups(ctx), timer::ticks(ctx));
 
-	    self.state.update(ctx, &self.settings)?;
+        if let Some(id) = self.next_state.take() {
+            self.switch(id)?;
+        }
+
+        let settings = &self.settings;
+        let res = &self.res;
+        let id = self.states.last_mut().unwrap().1.update(ctx, settings, res)?;
+        self.switch(id)?;
 
         timer::yield_now();
 
@@ -120,12 +190,22 @@ impl EventHandler for StateHandler {
     }
 
     fn draw(&mut self, ctx: &mut Context) -> GameResult<()> {
-        self.state.draw(ctx, &self.settings, &self.res)?;
+        let settings = &self.settings;
+        let res = &self.res;
+        self.states.last_mut().unwrap().1.draw(ctx, settings, res)?;
 
         graphics::present(ctx)
     }
 
     fn key_down_event(&mut self, ctx: &mut Context, keycode: KeyCode, repeat: bool) {
-        self.state.key_down_event(ctx, &self.settings, keycode, repeat);
+        let settings = &mut self.settings;
+        let id = self.states.last_mut().unwrap().1.key_down_event(ctx, settings, keycode, repeat);
+
+        // volume changes are heard immediately
+        self.res.sound.set_volume(self.settings.sound.volume);
+
+        if id != self.state_id() {
+            self.next_state = Some(id);
+        }
     }
 }
@@ -0,0 +1,167 @@
+use crate::engine::{
+    event::KeyCode,
+    graphics::{Text, Scale},
+    Context, GameResult,
+};
+
+use crate::settings::{self, Settings};
+use crate::rules::{TopOut, PREVIEW_COUNT_MIN, PREVIEW_COUNT_MAX};
+use crate::rotation::RotationSystemType;
+use super::{State, Resources, StateID};
+use super::menu::{self, navigate, cycle, step_level, generator_name, rotation_name, top_out_name, GENERATORS};
+
+const ROTATION_SYSTEMS: [RotationSystemType; 3] = [
+    RotationSystemType::RotationNES,
+    RotationSystemType::RotationSRS,
+    RotationSystemType::RotationARS,
+];
+
+const TOP_OUTS: [TopOut; 3] = [
+    TopOut::Classic,
+    TopOut::BlockOut,
+    TopOut::Guideline,
+];
+
+/// Volume change per key press.
+const VOLUME_STEP: f32 = 0.1;
+
+#[derive(Copy, Clone, PartialEq)]
+enum OptionItem {
+    Level,
+    Generator,
+    RotationSystem,
+    HardDrop,
+    Ghost,
+    Preview,
+    TopOut,
+    Volume,
+    Controls,
+    Back,
+}
+
+const ITEMS: [OptionItem; 10] = [
+    OptionItem::Level,
+    OptionItem::Generator,
+    OptionItem::RotationSystem,
+    OptionItem::HardDrop,
+    OptionItem::Ghost,
+    OptionItem::Preview,
+    OptionItem::TopOut,
+    OptionItem::Volume,
+    OptionItem::Controls,
+    OptionItem::Back,
+];
+
+fn on_off(enabled: bool) -> &'static str {
+    if enabled { "ON" } else { "OFF" }
+}
+
+/// Editor for the rules of the next game and the sound volume (saved to the settings file when leaving).
+pub struct OptionsState {
+    title_text: Text,
+    selected: usize,
+}
+
+impl OptionsState {
+    pub fn new(settings: &Settings, res: &Resources) -> OptionsState {
+        let mut title_text = Text::new("SETTINGS");
+        title_text.set_font(res.font, Scale::uniform(settings.font.size_popup));
+
+        OptionsState {
+            title_text,
+            selected: 0,
+        }
+    }
+
+    fn items(&self, settings: &Settings) -> Vec<String> {
+        let rules = &settings.rules;
+
+        ITEMS.iter()
+            .map(|item| match item {
+                OptionItem::Level => format!("LEVEL {}", rules.start_level),
+                OptionItem::Generator => format!("RANDOM {}", generator_name(rules.random_generator)),
+                OptionItem::RotationSystem => format!("ROTATION {}", rotation_name(rules.rotation_system)),
+                OptionItem::HardDrop => format!("HARD DROP {}", on_off(rules.hard_drop_enabled)),
+                OptionItem::Ghost => format!("GHOST {}", on_off(rules.ghost_enabled)),
+                OptionItem::Preview => format!("PREVIEW {}", rules.preview_count()),
+                OptionItem::TopOut => format!("TOP OUT {}", top_out_name(rules.top_out)),
+                OptionItem::Volume => format!("VOLUME {:.0}%", settings.sound.volume * 100.0),
+                OptionItem::Controls => "CONTROLS".to_string(),
+                OptionItem::Back => "BACK".to_string(),
+            })
+            .collect()
+    }
+
+    fn change(&self, settings: &mut Settings, forward: bool) {
+        let rules = &mut settings.rules;
+
+        match ITEMS[self.selected] {
+            OptionItem::Level => rules.start_level = step_level(rules.start_level, forward),
+            OptionItem::Generator => rules.random_generator = cycle(&GENERATORS, rules.random_generator, forward),
+            OptionItem::RotationSystem => rules.rotation_system = cycle(&ROTATION_SYSTEMS, rules.rotation_system, forward),
+            OptionItem::HardDrop => rules.hard_drop_enabled = !rules.hard_drop_enabled,
+            OptionItem::Ghost => rules.ghost_enabled = !rules.ghost_enabled,
+            OptionItem::Preview => {
+                let preview_count = rules.preview_count();
+                rules.set_preview_count(match forward {
+                    true if preview_count == PREVIEW_COUNT_MAX => PREVIEW_COUNT_MIN,
+                    true => preview_count + 1,
+                    false if preview_count == PREVIEW_COUNT_MIN => PREVIEW_COUNT_MAX,
+                    false => preview_count - 1,
+                });
+            },
+            OptionItem::TopOut => rules.top_out = cycle(&TOP_OUTS, rules.top_out, forward),
+            OptionItem::Volume => {
+                let step = if forward { VOLUME_STEP } else { -VOLUME_STEP };
+                // rounded to whole steps, so repeated changes do not drift
+                let volume = ((settings.sound.volume + step) / VOLUME_STEP).round() * VOLUME_STEP;
+                settings.sound.volume = volume.clamp(0.0, 1.0);
+            },
+            OptionItem::Controls | OptionItem::Back => (),
+        }
+    }
+
+    pub fn save(settings: &Settings) {
+        let path = settings::user_data_path(settings::SETTINGS_FILE);
+        let result = settings::write_atomic(&path, |file| settings.save(file).map_err(|err| err.into()));
+
+        if let Err(err) = result {
+            println!("Could not save settings {}: {}", path.display(), err);
+        }
+    }
+}
+
+impl State for OptionsState {
+    fn update(&mut self, _ctx: &mut Context, _settings: &Settings, _res: &Resources) -> GameResult<StateID> {
+        Ok(StateID::Settings)
+    }
+
+    fn draw(&mut self, ctx: &mut Context, settings: &Settings, res: &Resources) -> GameResult<()> {
+        menu::draw_screen(ctx, settings, res, &self.title_text, &self.items(settings), self.selected)
+    }
+
+    fn key_down_event(&mut self, _ctx: &mut Context, settings: &mut Settings, keycode: KeyCode, _repeat: bool) -> StateID {
+        match keycode {
+            KeyCode::Up | KeyCode::Down => self.selected = navigate(self.selected, ITEMS.len(), keycode),
+            KeyCode::Left => self.change(settings, false),
+            KeyCode::Right => self.change(settings, true),
+
+            KeyCode::Escape => {
+                OptionsState::save(settings);
+                return StateID::Menu;
+            },
+            KeyCode::Return => match ITEMS[self.selected] {
+                OptionItem::Controls => return StateID::Controls,
+                OptionItem::Back => {
+                    OptionsState::save(settings);
+                    return StateID::Menu;
+                },
+                _ => self.change(settings, true),
+            },
+
+            _ => (),
+        }
+
+        StateID::Settings
+    }
+}
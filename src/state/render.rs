@@ -0,0 +1,235 @@
+use crate::engine::{
+    vec::Vec2f,
+    graphics::{
+        self, DrawParam, Rect, Color, Text, Font, Scale,
+        spritebatch::SpriteBatch,
+    },
+    Context,
+};
+use std::cmp;
+
+use crate::tetrimino::{TileType, Tetrimino};
+use crate::settings::Settings;
+use crate::map::Map;
+
+/// Tiles of the largest tetrimino.
+const PREVIEW_EXTENT_MAX: f32 = 4.0;
+
+/// Width of the pending garbage meter (in tiles).
+const GARBAGE_METER_WIDTH: f32 = 0.25;
+
+/// Width of the outline of freshly locked tiles (in tiles).
+const OUTLINE_WIDTH: f32 = 0.125;
+
+/// Area of the sprite in the tileset.
+fn tile_src(tile_type: TileType, level: usize) -> Rect {
+    // garbage uses the I sprite (tinted by the caller)
+    let column = match tile_type {
+        TileType::Garbage => TileType::I as i32,
+        _ => tile_type as i32,
+    };
+    Rect::new((column as f32) * 0.125, ((level % 10) as f32) * 0.1, 0.125, 0.1)
+}
+
+/// Plain white area (the center of the I sprite), tinted by the caller.
+fn solid_src(level: usize) -> Rect {
+    let src = tile_src(TileType::I, level);
+    Rect::new(src.x + src.w * 0.375, src.y + src.h * 0.375, src.w * 0.25, src.h * 0.25)
+}
+
+pub fn draw_tile(batch: &mut SpriteBatch, tile_type: TileType, level: usize, pos: Vec2f, size: f32, color: Color) {
+    let size = Vec2f::new(size, size);
+    let draw_param = DrawParam::default()
+        .src(tile_src(tile_type, level))
+        .size(Some(size))
+        .dest(pos)
+        .color(color);
+
+    batch.add(draw_param);
+}
+
+/// Position and tile size of a map on screen.
+pub struct MapLayout {
+    position: Vec2f,
+    tile_size: f32,
+    hidden_rows: usize,
+}
+
+impl MapLayout {
+    /// Fits the visible part of the map into the map area at `position` (centered, at most the default tile size).
+    pub fn new(settings: &Settings, position: &Vec2f, map: &Map) -> MapLayout {
+        let width = map.width() as f32;
+        let height = map.visible_height() as f32;
+
+        let tile_size = settings.tile.size
+            .min(settings.map_size.x / width)
+            .min(settings.map_size.y / height);
+
+        let x = position.x + (settings.map_size.x - width * tile_size) / 2.0;
+        let y = position.y + (settings.map_size.y - height * tile_size) / 2.0;
+
+        MapLayout {
+            position: Vec2f::new(x, y),
+            tile_size,
+            hidden_rows: map.hidden_rows(),
+        }
+    }
+}
+
+pub fn draw_map_tile(batch: &mut SpriteBatch, tile_type: TileType, level: usize, layout: &MapLayout, x: i32, y: i32, color: Color) {
+    // hidden rows
+    let y = y - layout.hidden_rows as i32;
+    if y < 0 {
+        return;
+    }
+
+    let x = layout.position.x + x as f32 * layout.tile_size;
+    let y = layout.position.y + y as f32 * layout.tile_size;
+
+    draw_tile(batch, tile_type, level, Vec2f::new(x, y), layout.tile_size, color);
+}
+
+/// Draws all tiles of the map (`tile_color` tints non empty tiles).
+pub fn draw_map(batch: &mut SpriteBatch, map: &Map, level: usize, layout: &MapLayout, tile_color: impl Fn(usize, usize, TileType) -> Color) {
+    for y in 0..map.height() {
+        for x in 0..map.width() {
+            let tile_type = map.get(x, y);
+            let color = match tile_type {
+                TileType::Empty => graphics::WHITE,
+                _ => tile_color(x, y, tile_type),
+            };
+            draw_map_tile(batch, tile_type, level, layout, x as i32, y as i32, color);
+        }
+    }
+}
+
+/// Draws one segment per pending garbage row along the left edge of the map (from the bottom up).
+pub fn draw_garbage_meter(batch: &mut SpriteBatch, map: &Map, level: usize, layout: &MapLayout, rows: usize, color: Color) {
+    let width = layout.tile_size * GARBAGE_METER_WIDTH;
+    let bottom = layout.position.y + map.visible_height() as f32 * layout.tile_size;
+
+    for row in 0..cmp::min(rows, map.visible_height()) {
+        let y = bottom - (row + 1) as f32 * layout.tile_size;
+        let draw_param = DrawParam::default()
+            .src(tile_src(TileType::Garbage, level))
+            .size(Some(Vec2f::new(width, layout.tile_size)))
+            .dest(Vec2f::new(layout.position.x - width, y))
+            .color(color);
+
+        batch.add(draw_param);
+    }
+}
+
+/// Draws an outline around a group of map cells (edges between two cells of the group are left out).
+pub fn draw_outline(batch: &mut SpriteBatch, level: usize, layout: &MapLayout, cells: &[(i32, i32)], color: Color) {
+    let size = layout.tile_size;
+    let width = size * OUTLINE_WIDTH;
+
+    for &(x, y) in cells {
+        // hidden rows
+        let row = y - layout.hidden_rows as i32;
+        if row < 0 {
+            continue;
+        }
+
+        let left = layout.position.x + x as f32 * size;
+        let top = layout.position.y + row as f32 * size;
+        let edges = [
+            ((-1, 0), Vec2f::new(left, top), Vec2f::new(width, size)),
+            ((1, 0), Vec2f::new(left + size - width, top), Vec2f::new(width, size)),
+            ((0, -1), Vec2f::new(left, top), Vec2f::new(size, width)),
+            ((0, 1), Vec2f::new(left, top + size - width), Vec2f::new(size, width)),
+        ];
+
+        for &((x_off, y_off), dest, edge_size) in edges.iter() {
+            if cells.contains(&(x + x_off, y + y_off)) {
+                continue;
+            }
+
+            let draw_param = DrawParam::default()
+                .src(solid_src(level))
+                .size(Some(edge_size))
+                .dest(dest)
+                .color(color);
+
+            batch.add(draw_param);
+        }
+    }
+}
+
+pub fn draw_tetrimino_map(batch: &mut SpriteBatch, tet: &Tetrimino, level: usize, layout: &MapLayout, color: Color) {
+    for (x, y) in tet.cells() {
+        draw_map_tile(batch, tet.tile_type, level, layout, x, y, color);
+    }
+}
+
+/// Draws the tetrimino centered around `offset` (pieces wider than a tetrimino are scaled down).
+pub fn draw_tetrimino(batch: &mut SpriteBatch, tet: &Tetrimino, level: usize, offset: Vec2f, size: f32) {
+    let min_x = tet.tiles().map(|(x, _)| x).min().unwrap_or(0);
+    let max_x = tet.tiles().map(|(x, _)| x).max().unwrap_or(0);
+    let min_y = tet.tiles().map(|(_, y)| y).min().unwrap_or(0);
+    let max_y = tet.tiles().map(|(_, y)| y).max().unwrap_or(0);
+
+    let extent = cmp::max(max_x - min_x, max_y - min_y) + 1;
+    let size = size * (PREVIEW_EXTENT_MAX / extent as f32).min(1.0);
+
+    let center = Vec2f::new((min_x + max_x + 1) as f32 / 2.0, (min_y + max_y + 1) as f32 / 2.0);
+
+    for (tile_x, tile_y) in tet.tiles() {
+        let x = offset.x + (tile_x as f32 - center.x) * size;
+        let y = offset.y + (tile_y as f32 - center.y) * size;
+        let final_pos = Vec2f::new(x, y);
+        draw_tile(batch, tet.tile_type, level, final_pos, size, graphics::WHITE);
+    }
+}
+
+pub fn text_center_position(ctx: &mut Context, bounds: &Rect, text: &Text) -> Vec2f {
+    let x = bounds.x + (bounds.w - text.width(ctx) as f32) / 2.0;
+    let y = bounds.y + (bounds.h - text.height(ctx) as f32) / 2.0;
+
+    Vec2f {
+        x,
+        y,
+    }
+}
+
+pub fn draw_text(ctx: &mut Context, settings: &Settings, bounds: &Rect, text: &Text) {
+    let pos = text_center_position(ctx, bounds, text);
+          
+    graphics::queue_text(ctx, &text, pos, Some(settings.font.color));
+}
+
+pub fn draw_string(ctx: &mut Context, settings: &Settings, font: Font, bounds: &Rect, string: String) {
+    let mut text = Text::new(string);
+    text.set_font(font, Scale::uniform(settings.font.size_stats));
+    draw_text(ctx, settings, bounds, &text);
+}
+
+/// Draws the lines evenly distributed over the bounds.
+pub fn draw_lines(ctx: &mut Context, settings: &Settings, font: Font, bounds: &Rect, lines: Vec<String>) {
+    let line_h = bounds.h / lines.len() as f32;
+
+    for (i, line) in lines.into_iter().enumerate() {
+        let line_bounds = Rect::new(bounds.x, bounds.y + line_h * i as f32, bounds.w, line_h);
+        draw_string(ctx, settings, font, &line_bounds, line);
+    }
+}
+
+/// Draws the items evenly distributed over the bounds (the highlighted items are marked).
+pub fn draw_list(ctx: &mut Context, settings: &Settings, font: Font, size: f32, bounds: &Rect, items: &[String], highlighted: &[usize]) {
+    let item_h = bounds.h / items.len() as f32;
+
+    for (i, item) in items.iter().enumerate() {
+        let string = if highlighted.contains(&i) { format!("> {} <", item) } else { item.clone() };
+        let mut text = Text::new(string);
+        text.set_font(font, Scale::uniform(size));
+
+        let item_bounds = Rect::new(bounds.x, bounds.y + item_h * i as f32, bounds.w, item_h);
+        draw_text(ctx, settings, &item_bounds, &text);
+    }
+}
+
+/// Draws the items of a menu evenly distributed over the bounds (the selected item is marked).
+pub fn draw_menu(ctx: &mut Context, settings: &Settings, font: Font, bounds: &Rect, items: &[String], selected: usize) {
+    draw_list(ctx, settings, font, settings.font.size_menu, bounds, items, &[selected]);
+}
@@ -0,0 +1,103 @@
+use serde::{Serialize, Deserialize};
+use serde_json::Result;
+use std::{
+    cmp,
+    io::Write,
+};
+
+use crate::tetrimino::TileType;
+use crate::rules::GameMode;
+
+/// Per-game statistics.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Statistics {
+    /// Spawned pieces per tile type (I, O, T, S, Z, J, L).
+    pub pieces: [usize; 7],
+    /// Singles, doubles, triples and tetrises (or more lines at once).
+    pub clears: [usize; 4],
+    pub lines: usize,
+    /// Lines cleared by tetrises.
+    pub tetris_lines: usize,
+    /// Pieces since the last I piece.
+    pub drought: usize,
+    pub longest_drought: usize,
+    /// Pieces placed with more keys than necessary.
+    #[serde(default)]
+    pub finesse_faults: usize,
+}
+
+impl Statistics {
+    pub fn on_spawn(&mut self, tile_type: TileType) {
+        if let Some(count) = self.pieces.get_mut(tile_type as usize) {
+            *count += 1;
+        }
+
+        if tile_type == TileType::I {
+            self.drought = 0;
+        } else {
+            self.drought += 1;
+            self.longest_drought = cmp::max(self.longest_drought, self.drought);
+        }
+    }
+
+    pub fn on_clear(&mut self, lines: usize) {
+        if lines == 0 {
+            return;
+        }
+
+        self.clears[cmp::min(lines, 4) - 1] += 1;
+        self.lines += lines;
+        if lines >= 4 {
+            self.tetris_lines += lines;
+        }
+    }
+
+    pub fn piece_count(&self) -> usize {
+        self.pieces.iter().sum()
+    }
+
+    /// Share of the lines cleared by tetrises (0 to 1).
+    pub fn tetris_rate(&self) -> f32 {
+        if self.lines == 0 {
+            0.0
+        } else {
+            self.tetris_lines as f32 / self.lines as f32
+        }
+    }
+
+    /// Lines not cleared by tetrises.
+    pub fn burn(&self) -> usize {
+        self.lines - self.tetris_lines
+    }
+
+    /// Pieces per second after the given number of 60 Hz frames.
+    pub fn pps(&self, frames: usize) -> f32 {
+        if frames == 0 {
+            0.0
+        } else {
+            self.piece_count() as f32 * 60.0 / frames as f32
+        }
+    }
+}
+
+/// Summary of a game as written to disk.
+#[derive(Serialize)]
+pub struct Report<'a> {
+    pub nickname: &'a str,
+    pub seed: [u8; 32],
+    pub mode: GameMode,
+    pub score: usize,
+    pub lines: usize,
+    pub level: usize,
+    pub frames: usize,
+    pub tetris_rate: f32,
+    pub burn: usize,
+    pub pps: f32,
+    pub statistics: &'a Statistics,
+}
+
+impl<'a> Report<'a> {
+    pub fn save<W: Write>(&self, writer: W) -> Result<()> {
+        serde_json::to_writer(writer, self)
+    }
+}